@@ -0,0 +1,166 @@
+//! Parses the embedded rank files and lays out their lookup tables ahead of time, so that
+//! loading an encoding at runtime only copies the tables out of the binary.
+//!
+//! Only the encodings whose cargo feature is enabled are embedded. Rank files that parse to the
+//! same tables are embedded once, and `embedded.rs` / `embedded.d.ts` describe what was compiled
+//! in. With the `compress` feature only the front-coded tokens are embedded, deflated, and the
+//! tables are laid out at runtime instead.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use base64::{engine::general_purpose, Engine as _};
+
+#[allow(dead_code)]
+#[path = "src/vocab.rs"]
+mod vocab;
+
+#[path = "src/models.rs"]
+mod models;
+
+use models::MODEL_TO_ENCODING;
+use vocab::{Vocab, VocabBuilder};
+
+/// Every embeddable encoding and the rank file it uses. Each rank file has a cargo feature of
+/// the same name.
+const ENCODINGS: &[(&str, &str)] = &[
+    ("gpt2", "gpt2"),
+    ("r50k_base", "r50k_base"),
+    ("p50k_base", "p50k_base"),
+    ("p50k_edit", "p50k_base"),
+    ("cl100k_base", "cl100k_base"),
+];
+
+fn feature_enabled(feature: &str) -> bool {
+    env::var_os(format!("CARGO_FEATURE_{}", feature.to_uppercase())).is_some()
+}
+
+fn build_vocab(path: &str) -> Vocab {
+    let contents =
+        fs::read_to_string(path).unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+    let mut builder = VocabBuilder::default();
+    for (index, line) in contents.lines().enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        let (token, rank) = line
+            .split_once(' ')
+            .unwrap_or_else(|| panic!("{}:{}: malformed line", path, index + 1));
+        let token = general_purpose::STANDARD
+            .decode(token)
+            .unwrap_or_else(|e| panic!("{}:{}: {}", path, index + 1, e));
+        let rank = rank
+            .parse()
+            .unwrap_or_else(|e| panic!("{}:{}: {}", path, index + 1, e));
+        builder.push(&token, rank);
+    }
+
+    builder
+        .build()
+        .unwrap_or_else(|error| panic!("{}: {:?}", path, error))
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/vocab.rs");
+    println!("cargo:rerun-if-changed=src/models.rs");
+
+    // Lets the addon resolve the Node-API symbols from the host process on macOS
+    #[cfg(feature = "node")]
+    napi_build::setup();
+
+    let out_dir = env::var_os("OUT_DIR").unwrap();
+    let out_dir = Path::new(&out_dir);
+
+    // (rank file, rank file whose tables it shares)
+    let mut rank_files: Vec<(&str, &str)> = vec![];
+    let mut tables: Vec<(&str, Vec<u8>)> = vec![];
+    for &(_, ranks) in ENCODINGS {
+        if !feature_enabled(ranks) || rank_files.iter().any(|&(name, _)| name == ranks) {
+            continue;
+        }
+        let path = format!("ranks/{}.tiktoken", ranks);
+        println!("cargo:rerun-if-changed={}", path);
+
+        let vocab = build_vocab(&path);
+        let data = vocab.to_bytes();
+        let canonical = match tables.iter().find(|(_, existing)| *existing == data) {
+            Some(&(canonical, _)) => canonical,
+            None => {
+                let embedded = if feature_enabled("compress") {
+                    miniz_oxide::deflate::compress_to_vec(&vocab.to_front_coded(), 10)
+                } else {
+                    data.clone()
+                };
+                fs::write(out_dir.join(format!("{}.vocab", ranks)), embedded).unwrap();
+                tables.push((ranks, data));
+                ranks
+            }
+        };
+        rank_files.push((ranks, canonical));
+    }
+
+    let encodings: Vec<&str> = ENCODINGS
+        .iter()
+        .filter(|(_, ranks)| rank_files.iter().any(|&(name, _)| name == *ranks))
+        .map(|&(name, _)| name)
+        .collect();
+    let models: Vec<&str> = MODEL_TO_ENCODING
+        .iter()
+        .filter(|(_, encoding)| encodings.contains(encoding))
+        .map(|&(model, _)| model)
+        .collect();
+
+    let mut rust = String::from("// Generated by build.rs\n\n");
+    for (ranks, _) in &tables {
+        writeln!(
+            rust,
+            "static {}: &[u8] = include_bytes!(concat!(env!(\"OUT_DIR\"), \"/{}.vocab\"));",
+            ranks.to_uppercase(),
+            ranks
+        )
+        .unwrap();
+    }
+    writeln!(
+        rust,
+        "\n/// The encodings compiled into this build.\npub(crate) const ENCODINGS: &[&str] = &{:?};",
+        encodings
+    )
+    .unwrap();
+    rust.push_str(
+        "\n/// The cache key and precomputed tables of an embedded rank file. Rank files with \
+         identical\n/// contents share both.\npub(crate) fn vocab(ranks: &str) -> (&'static str, \
+         &'static [u8]) {\n    match ranks {\n",
+    );
+    for (ranks, canonical) in &rank_files {
+        writeln!(
+            rust,
+            "        {:?} => ({:?}, {}),",
+            ranks,
+            canonical,
+            canonical.to_uppercase()
+        )
+        .unwrap();
+    }
+    rust.push_str("        _ => panic!(\"{} is not embedded\", ranks),\n    }\n}\n");
+    fs::write(out_dir.join("embedded.rs"), rust).unwrap();
+
+    let union = |names: &[&str]| match names {
+        [] => String::from(" never"),
+        names => names
+            .iter()
+            .map(|name| format!("\n    | {:?}", name))
+            .collect(),
+    };
+    fs::write(
+        out_dir.join("embedded.d.ts"),
+        format!(
+            "\nexport type TiktokenEmbedding ={};\n\nexport type TiktokenModel ={};\n",
+            union(&encodings),
+            union(&models)
+        ),
+    )
+    .unwrap();
+}
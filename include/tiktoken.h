@@ -0,0 +1,159 @@
+/* Generated by cbindgen from src/capi.rs. Do not edit. */
+
+#ifndef TIKTOKEN_H
+#define TIKTOKEN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+// The values of the `allowed` argument of `tiktoken_encode`, which selects the special tokens
+// it turns into their ids.
+typedef enum {
+  // No special tokens.
+  TIKTOKEN_ALLOWED_SPECIAL_NONE = 0,
+  // Every special token of the encoding.
+  TIKTOKEN_ALLOWED_SPECIAL_ALL = 1,
+  // The tokens given in `allowed_special`.
+  TIKTOKEN_ALLOWED_SPECIAL_TOKENS = 2,
+} TiktokenAllowedSpecial;
+
+// The result of every function.
+typedef enum {
+  TIKTOKEN_STATUS_OK = 0,
+  // A null pointer, text that is not UTF-8, or an allowed special token the encoding lacks.
+  TIKTOKEN_STATUS_INVALID_ARGUMENT = 1,
+  // The ranks, special tokens or pattern do not form a valid encoding.
+  TIKTOKEN_STATUS_INVALID_ENCODING = 2,
+  // No encoding of that name, or for that model, was compiled in.
+  TIKTOKEN_STATUS_UNKNOWN_ENCODING = 3,
+  // The text contains a special token that is not allowed.
+  TIKTOKEN_STATUS_DISALLOWED_SPECIAL_TOKEN = 4,
+  // A token id is not in the encoding.
+  TIKTOKEN_STATUS_UNKNOWN_TOKEN = 5,
+  // The output buffer is too small. The required length has been written.
+  TIKTOKEN_STATUS_BUFFER_TOO_SMALL = 6,
+  // A bug in tiktoken. The encoding involved should not be used any more.
+  TIKTOKEN_STATUS_INTERNAL = 7,
+} TiktokenStatus;
+
+// An encoding created by `tiktoken_new`, `tiktoken_get_encoding` or
+// `tiktoken_encoding_for_model`, and released with `tiktoken_free`. An encoding may be used from
+// several threads at once.
+typedef struct TiktokenEncoding TiktokenEncoding;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// The message of the last error on the calling thread, or null if there was none. The string
+// stays valid until the next call that fails on the same thread.
+const char *tiktoken_last_error(void);
+
+// Creates an encoding from ranks in the `.tiktoken` text format, `n_special_tokens` special
+// tokens with their ids, and a split pattern. Parsed ranks are cached, so creating another
+// encoding from the same ranks is cheap.
+//
+// # Safety
+//
+// `ranks` must point to `ranks_len` readable bytes, `special_tokens` and `special_token_ids`
+// to `n_special_tokens` elements (they may be null if it is zero), and every string must be
+// nul-terminated.
+TiktokenStatus tiktoken_new(const uint8_t *ranks,
+                            size_t ranks_len,
+                            const char *const *special_tokens,
+                            const uint32_t *special_token_ids,
+                            size_t n_special_tokens,
+                            const char *pattern,
+                            TiktokenEncoding **encoding);
+
+// Creates one of the encodings compiled into the library, such as `cl100k_base`.
+//
+// # Safety
+//
+// `name` must be a nul-terminated string.
+TiktokenStatus tiktoken_get_encoding(const char *name, TiktokenEncoding **encoding);
+
+// Creates the encoding used by an OpenAI model, such as `text-embedding-ada-002`.
+//
+// # Safety
+//
+// `model` must be a nul-terminated string.
+TiktokenStatus tiktoken_encoding_for_model(const char *model, TiktokenEncoding **encoding);
+
+// Releases an encoding. Null is ignored.
+//
+// # Safety
+//
+// `encoding` must have been created by this library and not freed before.
+void tiktoken_free(TiktokenEncoding *encoding);
+
+// Writes the number of token ids of the encoding, i.e. the largest id plus one.
+//
+// # Safety
+//
+// `encoding` must be a live encoding.
+TiktokenStatus tiktoken_n_vocab(const TiktokenEncoding *encoding, size_t *n_vocab);
+
+// Encodes `text_len` bytes of UTF-8 text into `tokens`, turning the allowed special tokens
+// into their ids. Text containing any other special token is rejected with
+// `TIKTOKEN_STATUS_DISALLOWED_SPECIAL_TOKEN`; use `tiktoken_encode_ordinary` to encode it as
+// ordinary text.
+//
+// `allowed` is one of the `TiktokenAllowedSpecial` values. With
+// `TIKTOKEN_ALLOWED_SPECIAL_TOKENS`, every name in `allowed_special` must be a special token
+// of the encoding, or `TIKTOKEN_STATUS_INVALID_ARGUMENT` is returned.
+//
+// The number of tokens is written to `n_tokens`. If it exceeds `capacity`, nothing is written
+// to `tokens` and `TIKTOKEN_STATUS_BUFFER_TOO_SMALL` is returned, so passing a null buffer
+// queries the size.
+// `text_len` tokens are always enough.
+//
+// # Safety
+//
+// `encoding` must be a live encoding, `text` must point to `text_len` readable bytes,
+// `allowed_special` to `n_allowed_special` nul-terminated strings when `allowed` is
+// `TIKTOKEN_ALLOWED_SPECIAL_TOKENS`, and `tokens` to `capacity` writable elements.
+TiktokenStatus tiktoken_encode(const TiktokenEncoding *encoding,
+                               const char *text,
+                               size_t text_len,
+                               uint32_t allowed,
+                               const char *const *allowed_special,
+                               size_t n_allowed_special,
+                               uint32_t *tokens,
+                               size_t capacity,
+                               size_t *n_tokens);
+
+// Encodes `text_len` bytes of UTF-8 text into `tokens`, treating special tokens as ordinary
+// text. The buffer works as in `tiktoken_encode`.
+//
+// # Safety
+//
+// `encoding` must be a live encoding, `text` must point to `text_len` readable bytes, and
+// `tokens` to `capacity` writable elements.
+TiktokenStatus tiktoken_encode_ordinary(const TiktokenEncoding *encoding,
+                                        const char *text,
+                                        size_t text_len,
+                                        uint32_t *tokens,
+                                        size_t capacity,
+                                        size_t *n_tokens);
+
+// Decodes `n_tokens` tokens into `bytes`, which is not nul-terminated and need not be valid
+// UTF-8. The number of bytes is written to `n_bytes`, and a buffer that is too small works as
+// in `tiktoken_encode`.
+//
+// # Safety
+//
+// `encoding` must be a live encoding, `tokens` must point to `n_tokens` readable elements, and
+// `bytes` to `capacity` writable bytes.
+TiktokenStatus tiktoken_decode(const TiktokenEncoding *encoding,
+                               const uint32_t *tokens,
+                               size_t n_tokens,
+                               uint8_t *bytes,
+                               size_t capacity,
+                               size_t *n_bytes);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* TIKTOKEN_H */
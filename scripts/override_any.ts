@@ -7,6 +7,7 @@ for (const filename of [
   "./dist/bundler/_tiktoken.d.ts",
   "./dist/node/_tiktoken.d.ts",
   "./dist/web/_tiktoken.d.ts",
+  "./dist/cl100k_base/_tiktoken.d.ts",
 ]) {
   const sourceFile = project.getSourceFileOrThrow(filename);
   const cls = sourceFile.getFirstDescendantByKindOrThrow(
@@ -18,6 +19,11 @@ for (const filename of [
     .getParameterOrThrow("special_tokens")
     .set({ type: "Record<string, number>" });
 
+  cls
+    .getMethodOrThrow("with_special_tokens")
+    .getParameterOrThrow("special_tokens")
+    .set({ type: "Record<string, number>" });
+
   for (const method of ["encode", "encode_with_unstable"]) {
     cls
       .getMethodOrThrow(method)
@@ -30,9 +36,18 @@ for (const filename of [
       .set({ type: `"all" | string[]`, hasQuestionToken: true });
   }
 
+  cls
+    .getMemberOrThrow("special_tokens")
+    .set({ returnType: "Record<string, number>" });
+
   cls
     .getMemberOrThrow("token_byte_values")
     .set({ returnType: "Array<Array<number>>" });
 
+  sourceFile
+    .getFunctionOrThrow("tiktoken_to_binary")
+    .getParameterOrThrow("special_tokens")
+    .set({ type: "Record<string, number>" });
+
   sourceFile.saveSync();
 }
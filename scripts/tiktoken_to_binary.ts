@@ -0,0 +1,29 @@
+// Converts a `.tiktoken` rank file to the binary vocabulary format read by
+// `Tiktoken.from_binary`, e.g.
+//
+//   tsx scripts/tiktoken_to_binary.ts ranks/gpt2.tiktoken gpt2.json gpt2.bin
+//
+// where gpt2.json holds the rest of the encoding:
+//
+//   { "name": "gpt2", "pat_str": "...", "special_tokens": { "<|endoftext|>": 50256 } }
+import { readFileSync, writeFileSync } from "fs";
+import { tiktoken_to_binary } from "../dist/node/_tiktoken";
+
+const [ranksPath, encodingPath, outPath] = process.argv.slice(2);
+if (!ranksPath || !encodingPath || !outPath) {
+  console.error(
+    "Usage: tsx scripts/tiktoken_to_binary.ts <ranks.tiktoken> <encoding.json> <out.bin>"
+  );
+  process.exit(1);
+}
+
+const encoding = JSON.parse(readFileSync(encodingPath, "utf-8"));
+writeFileSync(
+  outPath,
+  tiktoken_to_binary(
+    readFileSync(ranksPath, "utf-8"),
+    encoding.special_tokens ?? {},
+    encoding.pat_str,
+    encoding.name
+  )
+);
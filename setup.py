@@ -7,6 +7,8 @@ setup(
         RustExtension(
             "tiktoken._tiktoken",
             binding=Binding.PyO3,
+            features=["python"],
+            args=["--no-default-features"],
             # Between our use of editable installs and wanting to use Rust for performance sensitive
             # code, it makes sense to just always use --release
             debug=False,
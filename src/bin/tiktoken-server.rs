@@ -0,0 +1,501 @@
+//! `tiktoken-server`, which loads encodings once and serves them over HTTP, so that services
+//! in any language can share one tokenizer. Every endpoint takes and returns JSON:
+//!
+//! - `GET /encodings` lists the loaded encodings.
+//! - `POST /encode` takes `text` and returns `tokens`.
+//! - `POST /count` takes `text` and returns `count`.
+//! - `POST /decode` takes `tokens` and returns `text`.
+//! - `POST /chunk` takes `text` and `max_tokens` and returns `chunks` of at most that many
+//!   tokens, each with its `text` and `tokens`.
+//!
+//! Requests name an `encoding` or a `model`, which may be left out when only one encoding is
+//! loaded. `text` and `tokens` may also be arrays, to handle a batch in one request, and the
+//! results are then arrays in the same order. `allowed_special` and `disallowed_special` work as
+//! in the JavaScript `Tiktoken.encode`. Errors are returned as
+//! `{"error": {"type": ..., "message": ...}}` with a 4xx or 5xx status.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::thread;
+
+use _tiktoken::{AllowedSpecial, DisallowedSpecial, Encoding, Error, Rank};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+#[derive(Parser)]
+#[clap(
+    name = "tiktoken-server",
+    version,
+    about = "Serves tiktoken encodings over HTTP"
+)]
+struct Args {
+    /// The address to listen on
+    #[clap(long, default_value = "127.0.0.1:7117", value_name = "ADDRESS")]
+    listen: String,
+
+    /// Listens on a Unix socket at this path instead
+    #[cfg(unix)]
+    #[clap(long, value_name = "PATH")]
+    unix_socket: Option<PathBuf>,
+
+    /// An encoding to serve. Every encoding compiled into this build is served by default
+    #[clap(long = "encoding", short, value_name = "NAME")]
+    encodings: Vec<String>,
+
+    /// The number of requests handled at once
+    #[clap(long, default_value = "4")]
+    threads: usize,
+
+    /// The largest request body accepted, in bytes
+    #[clap(long, default_value = "4194304", value_name = "BYTES")]
+    max_body_size: usize,
+
+    /// The largest number of texts or token lists accepted in one request
+    #[clap(long, default_value = "1024", value_name = "N")]
+    max_batch_size: usize,
+}
+
+struct State {
+    encodings: BTreeMap<String, Encoding>,
+    max_body_size: usize,
+    max_batch_size: usize,
+}
+
+/// An error response.
+struct ApiError {
+    status: u16,
+    kind: &'static str,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: u16, kind: &'static str, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            kind,
+            message: message.into(),
+        }
+    }
+
+    fn invalid_request(message: impl Into<String>) -> Self {
+        ApiError::new(400, "invalid_request", message)
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(error: Error) -> Self {
+        let (status, kind) = match error {
+            Error::UnknownEncoding { .. } | Error::UnknownModel { .. } => (404, "unknown_encoding"),
+            Error::UnknownSpecialToken { .. } => (400, "unknown_special_token"),
+            Error::DisallowedSpecialToken { .. } => (400, "disallowed_special_token"),
+            Error::UnknownToken { .. } | Error::NotASingleToken { .. } => (400, "unknown_token"),
+            Error::Construction(_) | Error::UnmergeableToken { .. } => (500, "internal"),
+        };
+        ApiError::new(status, kind, error.to_string())
+    }
+}
+
+/// A single value, or an array of them to handle a batch in one request.
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum Batch<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> Batch<T> {
+    fn try_map<U>(self, mut f: impl FnMut(T) -> Result<U, ApiError>) -> Result<Batch<U>, ApiError> {
+        Ok(match self {
+            Batch::One(value) => Batch::One(f(value)?),
+            Batch::Many(values) => {
+                Batch::Many(values.into_iter().map(f).collect::<Result<_, _>>()?)
+            }
+        })
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Batch::One(_) => 1,
+            Batch::Many(values) => values.len(),
+        }
+    }
+}
+
+/// `"all"` or a list of special tokens.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SpecialTokens {
+    Keyword(String),
+    Tokens(Vec<String>),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EncodeRequest {
+    encoding: Option<String>,
+    model: Option<String>,
+    text: Batch<String>,
+    allowed_special: Option<SpecialTokens>,
+    disallowed_special: Option<SpecialTokens>,
+    /// Only used by `/chunk`.
+    max_tokens: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DecodeRequest {
+    encoding: Option<String>,
+    model: Option<String>,
+    tokens: Batch<Vec<Rank>>,
+}
+
+#[derive(Serialize)]
+struct Chunk {
+    text: String,
+    tokens: Vec<Rank>,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let names: Vec<String> = if args.encodings.is_empty() {
+        Encoding::names()
+            .iter()
+            .map(|&name| String::from(name))
+            .collect()
+    } else {
+        args.encodings.clone()
+    };
+    let mut encodings = BTreeMap::new();
+    for name in names {
+        match Encoding::get(&name) {
+            Ok(encoding) => encodings.insert(name, encoding),
+            Err(error) => fail(&error.to_string()),
+        };
+    }
+    if encodings.is_empty() {
+        fail("No encodings are compiled into this build");
+    }
+
+    #[cfg(unix)]
+    let (server, address) = match &args.unix_socket {
+        Some(path) => (Server::http_unix(path), format!("unix:{}", path.display())),
+        None => (
+            Server::http(&args.listen),
+            format!("http://{}", args.listen),
+        ),
+    };
+    #[cfg(not(unix))]
+    let (server, address) = (
+        Server::http(&args.listen),
+        format!("http://{}", args.listen),
+    );
+    let server = Arc::new(server.unwrap_or_else(|error| fail(&error.to_string())));
+
+    let state = Arc::new(State {
+        encodings,
+        max_body_size: args.max_body_size,
+        max_batch_size: args.max_batch_size,
+    });
+    eprintln!(
+        "tiktoken-server: serving {} on {}",
+        state
+            .encodings
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(", "),
+        address
+    );
+
+    let workers: Vec<_> = (0..args.threads.max(1))
+        .map(|_| {
+            let server = Arc::clone(&server);
+            let state = Arc::clone(&state);
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    handle(&state, request);
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("tiktoken-server: {}", message);
+    process::exit(1);
+}
+
+fn handle(state: &State, mut request: Request) {
+    let result = route(state, &mut request);
+    let (status, body) = match result {
+        Ok(body) => (200, body),
+        Err(error) => (
+            error.status,
+            json!({ "error": { "type": error.kind, "message": error.message } }),
+        ),
+    };
+
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+        .expect("the header is valid");
+    let response = Response::from_data(body.to_string())
+        .with_status_code(status)
+        .with_header(content_type);
+    // The client may have disconnected, and there is nobody else to tell
+    let _ = request.respond(response);
+}
+
+fn route(state: &State, request: &mut Request) -> Result<serde_json::Value, ApiError> {
+    let path = request.url().split('?').next().unwrap_or("");
+    let method = request.method().clone();
+    match (path, method) {
+        ("/encodings", Method::Get) => Ok(list_encodings(state)),
+        ("/encode", Method::Post) => {
+            let request: EncodeRequest = read_json(state, request)?;
+            let encoding = select(state, &request.encoding, &request.model)?;
+            let tokens = encode(state, encoding, &request)?;
+            Ok(json!({ "tokens": tokens }))
+        }
+        ("/count", Method::Post) => {
+            let request: EncodeRequest = read_json(state, request)?;
+            let encoding = select(state, &request.encoding, &request.model)?;
+            let count = encode(state, encoding, &request)?.try_map(|tokens| Ok(tokens.len()))?;
+            Ok(json!({ "count": count }))
+        }
+        ("/chunk", Method::Post) => {
+            let request: EncodeRequest = read_json(state, request)?;
+            let encoding = select(state, &request.encoding, &request.model)?;
+            let max_tokens = match request.max_tokens {
+                Some(max_tokens) if max_tokens > 0 => max_tokens,
+                _ => {
+                    return Err(ApiError::invalid_request(
+                        "`max_tokens` must be a positive integer",
+                    ))
+                }
+            };
+            let chunks = encode(state, encoding, &request)?
+                .try_map(|tokens| chunk(encoding, &tokens, max_tokens))?;
+            Ok(json!({ "chunks": chunks }))
+        }
+        ("/decode", Method::Post) => {
+            let request: DecodeRequest = read_json(state, request)?;
+            check_batch_size(state, request.tokens.len())?;
+            let encoding = select(state, &request.encoding, &request.model)?;
+            let text = request.tokens.try_map(|tokens| {
+                let bytes = encoding.decode_bytes(&tokens)?;
+                Ok(String::from_utf8_lossy(&bytes).into_owned())
+            })?;
+            Ok(json!({ "text": text }))
+        }
+        ("/encodings" | "/encode" | "/count" | "/chunk" | "/decode", _) => Err(ApiError::new(
+            405,
+            "method_not_allowed",
+            format!("{} does not support {}", path, request.method()),
+        )),
+        _ => Err(ApiError::new(
+            404,
+            "not_found",
+            format!("There is no endpoint at {}", path),
+        )),
+    }
+}
+
+fn list_encodings(state: &State) -> serde_json::Value {
+    let encodings: Vec<_> = state
+        .encodings
+        .iter()
+        .map(|(name, encoding)| {
+            json!({
+                "name": name,
+                "n_vocab": encoding.n_vocab(),
+                "max_token_value": encoding.max_token_value(),
+                "eot_token": encoding.eot_token(),
+                "special_tokens": encoding.special_tokens(),
+                "pat_str": encoding.pattern(),
+            })
+        })
+        .collect();
+    json!({ "encodings": encodings })
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(
+    state: &State,
+    request: &mut Request,
+) -> Result<T, ApiError> {
+    let too_large = || {
+        ApiError::new(
+            413,
+            "payload_too_large",
+            format!("The request body exceeds {} bytes", state.max_body_size),
+        )
+    };
+    if request
+        .body_length()
+        .map_or(false, |length| length > state.max_body_size)
+    {
+        return Err(too_large());
+    }
+
+    // Requests without a length are cut off one byte past the limit
+    let mut body = vec![];
+    request
+        .as_reader()
+        .take(state.max_body_size as u64 + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| ApiError::invalid_request(format!("Failed to read the request: {}", e)))?;
+    if body.len() > state.max_body_size {
+        return Err(too_large());
+    }
+
+    serde_json::from_slice(&body)
+        .map_err(|e| ApiError::invalid_request(format!("Invalid request: {}", e)))
+}
+
+fn check_batch_size(state: &State, size: usize) -> Result<(), ApiError> {
+    if size > state.max_batch_size {
+        return Err(ApiError::new(
+            413,
+            "batch_too_large",
+            format!(
+                "The batch has {} items, but at most {} are accepted",
+                size, state.max_batch_size
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// The encoding named by a request, or the only one loaded if it names none.
+fn select<'a>(
+    state: &'a State,
+    encoding: &Option<String>,
+    model: &Option<String>,
+) -> Result<&'a Encoding, ApiError> {
+    let name = match (encoding, model) {
+        (Some(_), Some(_)) => {
+            return Err(ApiError::invalid_request(
+                "Give either `encoding` or `model`, not both",
+            ))
+        }
+        (Some(name), None) => name.clone(),
+        (None, Some(model)) => model_encoding(model)?,
+        (None, None) if state.encodings.len() == 1 => {
+            return Ok(state.encodings.values().next().expect("one encoding"))
+        }
+        (None, None) => {
+            return Err(ApiError::invalid_request(
+                "Several encodings are loaded, so `encoding` or `model` is required",
+            ))
+        }
+    };
+
+    state.encodings.get(&name).ok_or_else(|| {
+        ApiError::new(
+            404,
+            "unknown_encoding",
+            format!("The encoding {} is not loaded", name),
+        )
+    })
+}
+
+/// The name of the encoding a model uses. Only the loaded encodings are served, so this never
+/// builds a vocabulary.
+fn model_encoding(model: &str) -> Result<String, ApiError> {
+    Encoding::name_for_model(model)
+        .map(String::from)
+        .ok_or_else(|| {
+            ApiError::from(Error::UnknownModel {
+                model: String::from(model),
+            })
+        })
+}
+
+fn encode(
+    state: &State,
+    encoding: &Encoding,
+    request: &EncodeRequest,
+) -> Result<Batch<Vec<Rank>>, ApiError> {
+    check_batch_size(state, request.text.len())?;
+
+    let allowed_tokens: Vec<&str>;
+    let allowed = match &request.allowed_special {
+        None => AllowedSpecial::None,
+        Some(SpecialTokens::Keyword(keyword)) if keyword == "all" => AllowedSpecial::All,
+        Some(SpecialTokens::Tokens(tokens)) => {
+            allowed_tokens = tokens.iter().map(String::as_str).collect();
+            AllowedSpecial::Tokens(&allowed_tokens)
+        }
+        Some(SpecialTokens::Keyword(_)) => {
+            return Err(ApiError::invalid_request(
+                "Invalid value for allowed_special",
+            ))
+        }
+    };
+
+    let disallowed_tokens: Vec<&str>;
+    let disallowed = match &request.disallowed_special {
+        None => DisallowedSpecial::All,
+        Some(SpecialTokens::Keyword(keyword)) if keyword == "all" => DisallowedSpecial::All,
+        Some(SpecialTokens::Tokens(tokens)) => {
+            disallowed_tokens = tokens.iter().map(String::as_str).collect();
+            DisallowedSpecial::Tokens(&disallowed_tokens)
+        }
+        Some(SpecialTokens::Keyword(_)) => {
+            return Err(ApiError::invalid_request(
+                "Invalid value for disallowed_special",
+            ))
+        }
+    };
+
+    let texts = match &request.text {
+        Batch::One(text) => Batch::One(text.as_str()),
+        Batch::Many(texts) => Batch::Many(texts.iter().map(String::as_str).collect()),
+    };
+    texts.try_map(|text| Ok(encoding.encode(text, allowed, disallowed)?))
+}
+
+/// Splits `tokens` into runs of at most `max_tokens`, moving tokens to the next run rather than
+/// splitting a character between two runs.
+fn chunk(encoding: &Encoding, tokens: &[Rank], max_tokens: usize) -> Result<Vec<Chunk>, ApiError> {
+    // Decode every token once, `offsets[i]` being where token `i` starts in `bytes`
+    let mut bytes = vec![];
+    let mut offsets = Vec::with_capacity(tokens.len() + 1);
+    for &token in tokens {
+        offsets.push(bytes.len());
+        bytes.extend(encoding.decode_single_token_bytes(token)?);
+    }
+    offsets.push(bytes.len());
+
+    let mut chunks = vec![];
+    let mut start = 0;
+    while start < tokens.len() {
+        let mut end = tokens.len().min(start + max_tokens);
+        // A run ending inside a character decodes to invalid UTF-8, so it ends at the last token
+        // that also ends a character before the first invalid byte. A single token that is not
+        // valid UTF-8 on its own is kept as it is
+        if let Err(error) = std::str::from_utf8(&bytes[offsets[start]..offsets[end]]) {
+            let valid = offsets[start] + error.valid_up_to();
+            let ends_character =
+                |at: usize| at == valid || (at < valid && (bytes[at] as i8) >= -0x40);
+            while end > start + 1 && !ends_character(offsets[end]) {
+                end -= 1;
+            }
+        }
+
+        chunks.push(Chunk {
+            text: String::from_utf8_lossy(&bytes[offsets[start]..offsets[end]]).into_owned(),
+            tokens: tokens[start..end].to_vec(),
+        });
+        start = end;
+    }
+    Ok(chunks)
+}
@@ -0,0 +1,493 @@
+//! The `tiktoken` command-line tool, which encodes, decodes and counts tokens of files or
+//! standard input, and dumps or converts vocabularies.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use _tiktoken::{AllowedSpecial, DisallowedSpecial, Encoding, Error, Rank};
+use clap::{ArgEnum, Args, Parser, Subcommand};
+
+/// Any error not covered by the codes below.
+const EXIT_FAILURE: i32 = 1;
+/// The arguments are invalid, which is also the code of the errors reported by clap.
+const EXIT_USAGE: i32 = 2;
+/// The text contains a special token that is not allowed.
+const EXIT_DISALLOWED_SPECIAL_TOKEN: i32 = 3;
+/// A file or a standard stream could not be read or written.
+const EXIT_IO: i32 = 4;
+
+#[derive(Parser)]
+#[clap(
+    name = "tiktoken",
+    version,
+    about = "Encodes, decodes and counts tokens, and dumps or converts vocabularies",
+    after_help = "Exit codes: 1 for errors not listed here, 2 for invalid arguments, \
+                  3 when the text contains a special token that is not allowed, \
+                  4 when a file or a standard stream cannot be read or written."
+)]
+struct Cli {
+    #[clap(flatten)]
+    source: Source,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+/// Where the encoding comes from. Exactly one of `--encoding`, `--model` and `--vocab` is given.
+#[derive(Args)]
+struct Source {
+    /// An encoding compiled into this build, such as `cl100k_base`
+    #[clap(long, short, global = true, value_name = "NAME")]
+    encoding: Option<String>,
+
+    /// The encoding used by an OpenAI model, such as `text-embedding-ada-002`
+    #[clap(long, short, global = true, conflicts_with = "encoding")]
+    model: Option<String>,
+
+    /// A vocabulary file, whose format is guessed from its extension unless `--format` is given
+    #[clap(long, global = true, value_name = "FILE", conflicts_with_all = &["encoding", "model"])]
+    vocab: Option<PathBuf>,
+
+    /// The format of the `--vocab` file
+    #[clap(
+        long,
+        global = true,
+        arg_enum,
+        value_name = "FORMAT",
+        requires = "vocab"
+    )]
+    format: Option<VocabFormat>,
+
+    /// The JSON config written by `convert --to tiktoken`, with the pattern and special tokens of
+    /// a `.tiktoken` file
+    #[clap(long, global = true, value_name = "FILE", requires = "vocab")]
+    config: Option<PathBuf>,
+
+    /// The `vocab.bpe` merges that go with a GPT-2 `encoder.json`
+    #[clap(long, global = true, value_name = "FILE", requires = "vocab")]
+    merges: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Encodes text into token ids
+    Encode {
+        #[clap(flatten)]
+        special: Special,
+
+        /// How to write the token ids
+        #[clap(long, short, arg_enum, default_value = "lines")]
+        output: TokenFormat,
+
+        /// The UTF-8 text to encode, or `-` for standard input
+        file: Option<PathBuf>,
+    },
+    /// Decodes token ids into the bytes of the text
+    Decode {
+        /// How the token ids are written
+        #[clap(long, short, arg_enum, default_value = "lines")]
+        input: TokenFormat,
+
+        /// The token ids to decode, or `-` for standard input
+        file: Option<PathBuf>,
+    },
+    /// Prints the number of tokens in text
+    Count {
+        #[clap(flatten)]
+        special: Special,
+
+        /// The UTF-8 text to count, or `-` for standard input
+        file: Option<PathBuf>,
+    },
+    /// Prints every token id with its bytes, including the special tokens
+    Vocab {
+        /// Writes a JSON array of `{"token", "bytes", "special"}` objects instead of lines
+        #[clap(long)]
+        json: bool,
+    },
+    /// Writes the encoding in another vocabulary format
+    Convert {
+        /// The format to write
+        #[clap(long, arg_enum, value_name = "FORMAT")]
+        to: VocabFormat,
+
+        /// Where to write the vocabulary, standard output by default
+        #[clap(long, short, value_name = "FILE")]
+        output: Option<PathBuf>,
+
+        /// Where to write the JSON config of a `.tiktoken` vocabulary, which holds its name,
+        /// pattern and special tokens
+        #[clap(long, value_name = "FILE")]
+        config_output: Option<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+struct Special {
+    /// Special tokens to encode as such, separated by commas, or `all`
+    #[clap(long, value_name = "TOKENS", value_delimiter = ',')]
+    allowed_special: Vec<String>,
+
+    /// Special tokens that make encoding fail, separated by commas, `all` (the default) for
+    /// every special token that is not allowed, or `none` to encode them as ordinary text
+    #[clap(long, value_name = "TOKENS", value_delimiter = ',')]
+    disallowed_special: Vec<String>,
+}
+
+#[derive(Clone, Copy, ArgEnum)]
+enum TokenFormat {
+    /// A JSON array
+    Json,
+    /// One id per line. Any whitespace separates ids when decoding
+    Lines,
+    /// Little-endian `u32`s
+    Binary,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ArgEnum)]
+enum VocabFormat {
+    /// Ranks in the `.tiktoken` text format, along with a JSON config
+    Tiktoken,
+    /// The binary vocabulary format of `Tiktoken.from_binary`
+    Binary,
+    /// A Hugging Face `tokenizer.json`
+    Huggingface,
+    /// A GPT-2 `encoder.json`, along with its `vocab.bpe` merges
+    Gpt2,
+    /// A snapshot written by `Tiktoken.serialize`
+    Snapshot,
+}
+
+enum CliError {
+    Io {
+        path: Option<PathBuf>,
+        error: io::Error,
+    },
+    Tiktoken(Error),
+    /// Input that cannot be parsed.
+    Invalid(String),
+    /// Arguments that do not fit together.
+    Usage(String),
+}
+
+impl CliError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Io { .. } => EXIT_IO,
+            CliError::Tiktoken(Error::DisallowedSpecialToken { .. }) => {
+                EXIT_DISALLOWED_SPECIAL_TOKEN
+            }
+            CliError::Tiktoken(_) | CliError::Invalid(_) => EXIT_FAILURE,
+            CliError::Usage(_) => EXIT_USAGE,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Io {
+                path: Some(path),
+                error,
+            } => write!(f, "{}: {}", path.display(), error),
+            CliError::Io { path: None, error } => write!(f, "{}", error),
+            CliError::Tiktoken(error) => write!(f, "{}", error),
+            CliError::Invalid(message) | CliError::Usage(message) => f.write_str(message),
+        }
+    }
+}
+
+impl From<Error> for CliError {
+    fn from(error: Error) -> Self {
+        CliError::Tiktoken(error)
+    }
+}
+
+/// Errors of standard output and standard input.
+impl From<io::Error> for CliError {
+    fn from(error: io::Error) -> Self {
+        CliError::Io { path: None, error }
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    match run(cli) {
+        Ok(()) => {}
+        // Stop quietly when the output is piped into something like `head`
+        Err(CliError::Io { path: None, error }) if error.kind() == io::ErrorKind::BrokenPipe => {}
+        Err(error) => {
+            eprintln!("tiktoken: {}", error);
+            process::exit(error.exit_code());
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), CliError> {
+    let encoding = cli.source.load()?;
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+
+    match cli.command {
+        Command::Encode {
+            special,
+            output,
+            file,
+        } => {
+            let text = read_text(file.as_deref())?;
+            let tokens = special.encode(&encoding, &text)?;
+            write_tokens(&mut out, &tokens, output)?;
+        }
+        Command::Decode { input, file } => {
+            let tokens = parse_tokens(&read_input(file.as_deref())?, input)?;
+            out.write_all(&encoding.decode_bytes(&tokens)?)?;
+        }
+        Command::Count { special, file } => {
+            let text = read_text(file.as_deref())?;
+            writeln!(out, "{}", special.encode(&encoding, &text)?.len())?;
+        }
+        Command::Vocab { json } => write_vocab(&mut out, &encoding, json)?,
+        Command::Convert {
+            to,
+            output,
+            config_output,
+        } => {
+            let data = match to {
+                VocabFormat::Tiktoken => encoding.export_ranks().into_bytes(),
+                VocabFormat::Binary => encoding.to_binary(),
+                VocabFormat::Huggingface => encoding.export_huggingface()?.into_bytes(),
+                VocabFormat::Snapshot => encoding.serialize(),
+                VocabFormat::Gpt2 => {
+                    return Err(CliError::Usage(String::from(
+                        "Vocabularies cannot be converted to the GPT-2 format",
+                    )))
+                }
+            };
+
+            match (&config_output, to) {
+                (Some(path), VocabFormat::Tiktoken) => write_file(path, encoding.export_config())?,
+                (Some(_), _) => {
+                    return Err(CliError::Usage(String::from(
+                        "--config-output only applies to `--to tiktoken`",
+                    )))
+                }
+                (None, _) => {}
+            }
+
+            match output {
+                Some(path) => write_file(&path, data)?,
+                None => out.write_all(&data)?,
+            }
+        }
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+impl Source {
+    fn load(&self) -> Result<Encoding, CliError> {
+        if let Some(name) = &self.encoding {
+            return Ok(Encoding::get(name)?);
+        }
+        if let Some(model) = &self.model {
+            return Ok(Encoding::for_model(model)?);
+        }
+        let path = self.vocab.as_deref().ok_or_else(|| {
+            CliError::Usage(String::from(
+                "One of --encoding, --model or --vocab is required",
+            ))
+        })?;
+
+        let format = match self.format {
+            Some(format) => format,
+            None if self.merges.is_some() => VocabFormat::Gpt2,
+            None => guess_format(path)?,
+        };
+        match format {
+            VocabFormat::Tiktoken => {
+                let config = self.config.as_deref().ok_or_else(|| {
+                    CliError::Usage(String::from(
+                        "A .tiktoken vocabulary needs --config for its pattern",
+                    ))
+                })?;
+                load_tiktoken(&read_string(path)?, &read_string(config)?)
+            }
+            VocabFormat::Binary => Ok(Encoding::from_binary(&read_file(path)?)?),
+            VocabFormat::Huggingface => Ok(Encoding::from_huggingface(&read_string(path)?)?),
+            VocabFormat::Gpt2 => {
+                let merges = self.merges.as_deref().ok_or_else(|| {
+                    CliError::Usage(String::from(
+                        "A GPT-2 encoder.json needs --merges for its vocab.bpe",
+                    ))
+                })?;
+                Ok(Encoding::from_gpt2(
+                    &read_string(path)?,
+                    &read_string(merges)?,
+                )?)
+            }
+            VocabFormat::Snapshot => Ok(Encoding::deserialize(&read_file(path)?)?),
+        }
+    }
+}
+
+fn guess_format(path: &Path) -> Result<VocabFormat, CliError> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("tiktoken") => Ok(VocabFormat::Tiktoken),
+        Some("json") => Ok(VocabFormat::Huggingface),
+        _ => Err(CliError::Usage(format!(
+            "Cannot tell the format of {} from its extension, use --format",
+            path.display()
+        ))),
+    }
+}
+
+/// The `.tiktoken` ranks and the JSON config written by `Encoding::export_config`.
+fn load_tiktoken(ranks: &str, config: &str) -> Result<Encoding, CliError> {
+    #[derive(serde::Deserialize)]
+    struct Config {
+        name: Option<String>,
+        pat_str: String,
+        #[serde(default)]
+        special_tokens: std::collections::HashMap<String, Rank>,
+    }
+
+    let config: Config = serde_json::from_str(config)
+        .map_err(|e| CliError::Invalid(format!("Invalid config: {}", e)))?;
+    let encoding = Encoding::new(ranks, config.special_tokens, &config.pat_str)?;
+    Ok(match config.name {
+        Some(name) => encoding.with_name(&name),
+        None => encoding,
+    })
+}
+
+impl Special {
+    fn encode(&self, encoding: &Encoding, text: &str) -> Result<Vec<Rank>, Error> {
+        let allowed_tokens: Vec<&str> = self.allowed_special.iter().map(String::as_str).collect();
+        let disallowed_tokens: Vec<&str> =
+            self.disallowed_special.iter().map(String::as_str).collect();
+
+        let allowed = match allowed_tokens.as_slice() {
+            [] => AllowedSpecial::None,
+            tokens if tokens.contains(&"all") => AllowedSpecial::All,
+            tokens => AllowedSpecial::Tokens(tokens),
+        };
+        let disallowed = match disallowed_tokens.as_slice() {
+            [] => DisallowedSpecial::All,
+            tokens if tokens.contains(&"all") => DisallowedSpecial::All,
+            tokens if tokens.contains(&"none") => DisallowedSpecial::None,
+            tokens => DisallowedSpecial::Tokens(tokens),
+        };
+        encoding.encode(text, allowed, disallowed)
+    }
+}
+
+fn write_tokens(out: &mut impl Write, tokens: &[Rank], format: TokenFormat) -> io::Result<()> {
+    match format {
+        TokenFormat::Json => {
+            serde_json::to_writer(&mut *out, tokens)?;
+            writeln!(out)
+        }
+        TokenFormat::Lines => tokens
+            .iter()
+            .try_for_each(|token| writeln!(out, "{}", token)),
+        TokenFormat::Binary => tokens
+            .iter()
+            .try_for_each(|token| out.write_all(&token.to_le_bytes())),
+    }
+}
+
+fn parse_tokens(input: &[u8], format: TokenFormat) -> Result<Vec<Rank>, CliError> {
+    match format {
+        TokenFormat::Json => serde_json::from_slice(input)
+            .map_err(|e| CliError::Invalid(format!("Invalid token ids: {}", e))),
+        TokenFormat::Lines => std::str::from_utf8(input)
+            .map_err(|_| CliError::Invalid(String::from("Token ids are not UTF-8")))?
+            .split_whitespace()
+            .map(|token| {
+                token
+                    .parse()
+                    .map_err(|_| CliError::Invalid(format!("Invalid token id: {}", token)))
+            })
+            .collect(),
+        TokenFormat::Binary if input.len() % 4 != 0 => Err(CliError::Invalid(format!(
+            "{} bytes is not a whole number of little-endian u32 token ids",
+            input.len()
+        ))),
+        TokenFormat::Binary => Ok(input
+            .chunks_exact(4)
+            .map(|bytes| Rank::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect()),
+    }
+}
+
+fn write_vocab(out: &mut impl Write, encoding: &Encoding, json: bool) -> io::Result<()> {
+    // Special token ids can be far beyond the ordinary ranks, so only existing ids are visited
+    let mut tokens: Vec<(Rank, &[u8], bool)> = encoding
+        .ordinary_tokens()
+        .map(|(token, bytes)| (token, bytes, false))
+        .chain(
+            encoding
+                .special_tokens()
+                .into_iter()
+                .map(|(name, token)| (token, name.as_bytes(), true)),
+        )
+        .collect();
+    tokens.sort_unstable_by_key(|&(token, _, _)| token);
+    let tokens = tokens.into_iter();
+
+    if !json {
+        for (token, bytes, special) in tokens {
+            let marker = if special { " (special)" } else { "" };
+            writeln!(out, "{}\t{:?}{}", token, bstr::BStr::new(&bytes), marker)?;
+        }
+        return Ok(());
+    }
+
+    let entries: Vec<_> = tokens
+        .map(|(token, bytes, special)| {
+            serde_json::json!({ "token": token, "bytes": bytes, "special": special })
+        })
+        .collect();
+    serde_json::to_writer(&mut *out, &entries)?;
+    writeln!(out)
+}
+
+/// Reads `path`, or standard input if it is missing or `-`.
+fn read_input(path: Option<&Path>) -> Result<Vec<u8>, CliError> {
+    match path {
+        Some(path) if path != Path::new("-") => read_file(path),
+        _ => {
+            let mut input = vec![];
+            io::stdin().read_to_end(&mut input)?;
+            Ok(input)
+        }
+    }
+}
+
+fn read_text(path: Option<&Path>) -> Result<String, CliError> {
+    String::from_utf8(read_input(path)?)
+        .map_err(|_| CliError::Invalid(String::from("The text is not UTF-8")))
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, CliError> {
+    fs::read(path).map_err(|error| CliError::Io {
+        path: Some(path.to_path_buf()),
+        error,
+    })
+}
+
+fn read_string(path: &Path) -> Result<String, CliError> {
+    String::from_utf8(read_file(path)?)
+        .map_err(|_| CliError::Invalid(format!("{} is not UTF-8", path.display())))
+}
+
+fn write_file(path: &Path, data: impl AsRef<[u8]>) -> Result<(), CliError> {
+    fs::write(path, data).map_err(|error| CliError::Io {
+        path: Some(path.to_path_buf()),
+        error,
+    })
+}
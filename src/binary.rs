@@ -0,0 +1,208 @@
+//! A binary alternative to the `.tiktoken` text format, which also carries the name, split
+//! pattern and special tokens of an encoding so that it can be loaded from a single buffer.
+//!
+//! Integers are LEB128 and strings are UTF-8 prefixed with their length in bytes:
+//!
+//! ```text
+//! "TKBV", version
+//! name, pattern
+//! special token count, (name, id) for each special token
+//! token count, (length, bytes) for each token in rank order
+//! ```
+//!
+//! An empty name means the encoding is unnamed, and an empty token marks an unused rank.
+
+use rustc_hash::FxHashMap as HashMap;
+
+use crate::vocab::{read_varint, write_varint, Rank, Vocab, VocabBuilder};
+use crate::{build_vocab, ConstructionError};
+
+const MAGIC: &[u8; 4] = b"TKBV";
+
+/// The version written by [`write`]; [`read`] rejects any other.
+pub(crate) const VERSION: u32 = 1;
+
+/// The header of a binary vocabulary. The tokens are parsed separately by [`parse_tokens`], so
+/// that they can be looked up in the cache first.
+pub(crate) struct BinaryEncoding<'a> {
+    pub(crate) name: Option<String>,
+    pub(crate) pattern: String,
+    pub(crate) special_tokens: HashMap<String, Rank>,
+    pub(crate) tokens: &'a [u8],
+    /// Where `tokens` starts in the buffer, for error offsets.
+    pub(crate) tokens_offset: usize,
+}
+
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+    /// The length of the whole buffer, which `data` is the unread end of.
+    len: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Reader {
+            data,
+            len: data.len(),
+        }
+    }
+
+    pub(crate) fn offset(&self) -> usize {
+        self.len - self.data.len()
+    }
+
+    fn error(&self) -> ConstructionError {
+        ConstructionError::MalformedBinary {
+            offset: self.offset(),
+        }
+    }
+
+    pub(crate) fn varint(&mut self) -> Result<u32, ConstructionError> {
+        read_varint(&mut self.data).ok_or_else(|| self.error())
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], ConstructionError> {
+        let offset = self.offset();
+        let len = self.varint()? as usize;
+        if len > self.data.len() {
+            return Err(ConstructionError::MalformedBinary { offset });
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    /// The unread end of the buffer.
+    pub(crate) fn rest(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub(crate) fn string(&mut self) -> Result<String, ConstructionError> {
+        let offset = self.offset();
+        let bytes = self.bytes()?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| ConstructionError::MalformedBinary { offset })
+    }
+}
+
+/// The parts of an encoding besides its ordinary tokens.
+pub(crate) struct Metadata {
+    pub(crate) name: Option<String>,
+    pub(crate) pattern: String,
+    pub(crate) special_tokens: HashMap<String, Rank>,
+}
+
+pub(crate) fn read_metadata(reader: &mut Reader) -> Result<Metadata, ConstructionError> {
+    let name = Some(reader.string()?).filter(|name| !name.is_empty());
+    let pattern = reader.string()?;
+
+    let count = reader.varint()?;
+    let mut special_tokens = HashMap::default();
+    for _ in 0..count {
+        let offset = reader.offset();
+        let token = reader.string()?;
+        let id = reader.varint()?;
+        if token.is_empty() {
+            return Err(ConstructionError::InvalidSpecialTokens {
+                tokens: vec![token],
+            });
+        }
+        if special_tokens.insert(token, id).is_some() {
+            return Err(ConstructionError::MalformedBinary { offset });
+        }
+    }
+
+    Ok(Metadata {
+        name,
+        pattern,
+        special_tokens,
+    })
+}
+
+pub(crate) fn read(data: &[u8]) -> Result<BinaryEncoding<'_>, ConstructionError> {
+    if !data.starts_with(MAGIC) {
+        return Err(ConstructionError::MalformedBinary { offset: 0 });
+    }
+    let mut reader = Reader::new(data);
+    reader.data = &data[MAGIC.len()..];
+
+    let version = reader.varint()?;
+    if version != VERSION {
+        return Err(ConstructionError::UnsupportedBinaryVersion { version });
+    }
+
+    let Metadata {
+        name,
+        pattern,
+        special_tokens,
+    } = read_metadata(&mut reader)?;
+
+    Ok(BinaryEncoding {
+        name,
+        pattern,
+        special_tokens,
+        tokens_offset: reader.offset(),
+        tokens: reader.data,
+    })
+}
+
+pub(crate) fn parse_tokens(
+    tokens: &[u8],
+    tokens_offset: usize,
+) -> Result<Vocab, ConstructionError> {
+    let mut reader = Reader::new(tokens);
+    reader.len += tokens_offset;
+
+    let count = reader.varint()?;
+    // Every token takes at least a byte, so this bounds the allocation for truncated input
+    let mut builder = VocabBuilder::with_capacity((count as usize).min(tokens.len()), tokens.len());
+    for rank in 0..count {
+        builder.push(reader.bytes()?, rank);
+    }
+    if !reader.data.is_empty() {
+        return Err(reader.error());
+    }
+
+    build_vocab(builder)
+}
+
+fn write_bytes(data: &mut Vec<u8>, bytes: &[u8]) {
+    write_varint(data, bytes.len() as u32);
+    data.extend_from_slice(bytes);
+}
+
+/// Writes the name, pattern and special tokens of an encoding, as read by [`read_metadata`].
+pub(crate) fn write_metadata(
+    data: &mut Vec<u8>,
+    name: Option<&str>,
+    pattern: &str,
+    special_tokens: &HashMap<String, Rank>,
+) {
+    write_bytes(data, name.unwrap_or_default().as_bytes());
+    write_bytes(data, pattern.as_bytes());
+
+    let mut special_tokens: Vec<_> = special_tokens.iter().collect();
+    special_tokens.sort();
+    write_varint(data, special_tokens.len() as u32);
+    for (token, &id) in special_tokens {
+        write_bytes(data, token.as_bytes());
+        write_varint(data, id);
+    }
+}
+
+pub(crate) fn write(
+    name: Option<&str>,
+    pattern: &str,
+    special_tokens: &HashMap<String, Rank>,
+    vocab: &Vocab,
+) -> Vec<u8> {
+    let mut data = Vec::from(&MAGIC[..]);
+    write_varint(&mut data, VERSION);
+    write_metadata(&mut data, name, pattern, special_tokens);
+
+    let n_ranks = vocab.max_rank().map_or(0, |rank| rank + 1);
+    write_varint(&mut data, n_ranks);
+    for rank in 0..n_ranks {
+        write_bytes(&mut data, vocab.get(rank).unwrap_or_default());
+    }
+    data
+}
@@ -0,0 +1,176 @@
+//! The printable alphabet GPT-2 uses to store byte-level tokens as text, found in its
+//! `encoder.json` and `vocab.bpe` and in Hugging Face `tokenizer.json` files.
+//!
+//! Printable Latin-1 bytes stand for themselves, and the 68 remaining bytes are assigned the
+//! code points from U+0100 in byte order.
+
+use rustc_hash::FxHashMap as HashMap;
+
+use crate::vocab::{Rank, Vocab, VocabBuilder};
+use crate::{build_vocab, ConstructionError, ENDOFTEXT};
+
+/// The split pattern of the original GPT-2 encoder.
+pub(crate) const GPT2_PATTERN: &str =
+    "'s|'t|'re|'ve|'m|'ll|'d| ?\\p{L}+| ?\\p{N}+| ?[^\\s\\p{L}\\p{N}]+|\\s+(?!\\S)|\\s+";
+
+/// Tokens of `encoder.json` that the original GPT-2 encoder treats as special.
+const GPT2_SPECIAL_TOKENS: &[&str] = &[ENDOFTEXT, "<|startoftext|>"];
+
+fn is_printable(byte: u8) -> bool {
+    matches!(byte, b'!'..=b'~' | 0xa1..=0xac | 0xae..=0xff)
+}
+
+fn byte_to_char(byte: u8) -> char {
+    let shifted = match byte {
+        _ if is_printable(byte) => return char::from(byte),
+        0x00..=0x20 => byte,
+        0x7f..=0xa0 => byte - 0x7f + 0x21,
+        _ => 0x43, // 0xad
+    };
+    char::from_u32(0x100 + u32::from(shifted)).unwrap()
+}
+
+fn char_to_byte(c: char) -> Option<u8> {
+    match c as u32 {
+        byte @ 0..=0xff if is_printable(byte as u8) => Some(byte as u8),
+        shifted @ 0x100..=0x120 => Some((shifted - 0x100) as u8),
+        shifted @ 0x121..=0x142 => Some((shifted - 0x121 + 0x7f) as u8),
+        0x143 => Some(0xad),
+        _ => None,
+    }
+}
+
+pub(crate) fn encode(bytes: &[u8]) -> String {
+    bytes.iter().copied().map(byte_to_char).collect()
+}
+
+pub(crate) fn decode(token: &str) -> Option<Vec<u8>> {
+    token.chars().map(char_to_byte).collect()
+}
+
+/// Builds a vocabulary from tokens in the byte-level alphabet and their ids.
+///
+/// Byte-level BPE applies `merges` in order, while tiktoken merges the pair whose result has
+/// the lowest rank, and ranks double as ids. The two agree when every merge produces a token
+/// with a higher id than the merge before it, which is checked here.
+pub(crate) fn vocab_from_merges<'a>(
+    tokens: &HashMap<String, u32>,
+    merges: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Result<Vocab, ConstructionError> {
+    let mut builder = VocabBuilder::with_capacity(tokens.len(), tokens.len() * 8);
+    for (token, &id) in tokens {
+        let bytes = decode(token).ok_or_else(|| ConstructionError::InvalidByteLevelToken {
+            token: token.clone(),
+        })?;
+        builder.push(&bytes, id);
+    }
+
+    let mut previous = None;
+    for (index, (left, right)) in merges.into_iter().enumerate() {
+        let merge = index + 1;
+        for part in [left, right] {
+            if !tokens.contains_key(part) {
+                return Err(ConstructionError::UnknownMergeToken {
+                    merge,
+                    token: String::from(part),
+                });
+            }
+        }
+
+        let merged = format!("{}{}", left, right);
+        let id = match tokens.get(&merged) {
+            Some(&id) => id,
+            None => {
+                return Err(ConstructionError::UnknownMergeToken {
+                    merge,
+                    token: merged,
+                })
+            }
+        };
+        if previous.map_or(false, |previous| id <= previous) {
+            return Err(ConstructionError::MergeOrderMismatch {
+                merge,
+                token: merged,
+                id,
+            });
+        }
+        previous = Some(id);
+    }
+
+    build_vocab(builder)
+}
+
+/// Loads the `encoder.json` and `vocab.bpe` files of the original GPT-2 release, or of models
+/// derived from it. Returns the vocabulary and the special tokens found in `encoder.json`.
+pub(crate) fn import_gpt2(
+    encoder_json: &str,
+    vocab_bpe: &str,
+) -> Result<(Vocab, HashMap<String, Rank>), ConstructionError> {
+    let mut tokens: HashMap<String, u32> =
+        serde_json::from_str(encoder_json).map_err(|e| ConstructionError::InvalidEncoderJson {
+            message: e.to_string(),
+        })?;
+
+    let mut special_tokens = HashMap::default();
+    for &token in GPT2_SPECIAL_TOKENS {
+        if let Some(id) = tokens.remove(token) {
+            special_tokens.insert(String::from(token), id);
+        }
+    }
+
+    let mut merges = vec![];
+    for (index, line) in vocab_bpe.lines().enumerate() {
+        if line.is_empty() || (index == 0 && line.starts_with("#version")) {
+            continue;
+        }
+        match line.split_once(' ') {
+            Some(merge) => merges.push(merge),
+            None => return Err(ConstructionError::MalformedMerge { line: index + 1 }),
+        }
+    }
+
+    Ok((vocab_from_merges(&tokens, merges)?, special_tokens))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, import_gpt2};
+    use crate::ConstructionError;
+
+    #[test]
+    fn maps_every_byte_to_a_distinct_printable_char() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let text = encode(&bytes);
+        assert_eq!(text.chars().count(), 256);
+        assert!(text.chars().all(|c| !c.is_whitespace() && !c.is_control()));
+        assert_eq!(decode(&text), Some(bytes));
+
+        assert_eq!(encode(b" hello\n"), "\u{120}hello\u{10a}");
+        assert_eq!(decode("\u{144}"), None);
+    }
+
+    #[test]
+    fn imports_gpt2_files() {
+        let encoder_json =
+            r#"{"a": 0, "b": 1, "\u0120": 2, "ab": 3, "\u0120ab": 4, "<|endoftext|>": 5}"#;
+        let (vocab, special_tokens) =
+            import_gpt2(encoder_json, "#version: 0.2\na b\n\u{120} ab\n").unwrap();
+        assert_eq!(vocab.len(), 5);
+        assert_eq!(vocab.get(4), Some(&b" ab"[..]));
+        assert_eq!(special_tokens.len(), 1);
+        assert_eq!(special_tokens["<|endoftext|>"], 5);
+
+        assert_eq!(
+            import_gpt2(encoder_json, "#version: 0.2\n\u{120} ab\na b").err(),
+            Some(ConstructionError::MergeOrderMismatch {
+                merge: 2,
+                token: "ab".to_string(),
+                id: 3
+            })
+        );
+        assert_eq!(
+            import_gpt2(encoder_json, "#version: 0.2\nab").err(),
+            Some(ConstructionError::MalformedMerge { line: 2 })
+        );
+    }
+}
@@ -0,0 +1,556 @@
+//! A C ABI over [`Encoding`] for hosts that cannot link Rust directly. `include/tiktoken.h` is
+//! generated from this file with `cbindgen --config cbindgen.toml --output include/tiktoken.h`.
+//!
+//! Every function returns a [`TiktokenStatus`], and on failure the message of the error can be
+//! read with [`tiktoken_last_error`]. Results are written through out pointers, and token and
+//! byte sequences into buffers owned by the caller.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::{ptr, slice, str};
+
+use crate::{AllowedSpecial, DisallowedSpecial, Encoding, Error};
+
+/// An encoding created by `tiktoken_new`, `tiktoken_get_encoding` or
+/// `tiktoken_encoding_for_model`, and released with `tiktoken_free`. An encoding may be used from
+/// several threads at once.
+pub struct TiktokenEncoding {
+    encoding: Encoding,
+}
+
+/// The result of every function.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TiktokenStatus {
+    Ok = 0,
+    /// A null pointer, text that is not UTF-8, or an allowed special token the encoding lacks.
+    InvalidArgument = 1,
+    /// The ranks, special tokens or pattern do not form a valid encoding.
+    InvalidEncoding = 2,
+    /// No encoding of that name, or for that model, was compiled in.
+    UnknownEncoding = 3,
+    /// The text contains a special token that is not allowed.
+    DisallowedSpecialToken = 4,
+    /// A token id is not in the encoding.
+    UnknownToken = 5,
+    /// The output buffer is too small. The required length has been written.
+    BufferTooSmall = 6,
+    /// A bug in tiktoken. The encoding involved should not be used any more.
+    Internal = 7,
+}
+
+/// The values of the `allowed` argument of `tiktoken_encode`, which selects the special tokens
+/// it turns into their ids.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TiktokenAllowedSpecial {
+    /// No special tokens.
+    None = 0,
+    /// Every special token of the encoding.
+    All = 1,
+    /// The tokens given in `allowed_special`.
+    Tokens = 2,
+}
+
+impl TiktokenAllowedSpecial {
+    /// Checks a value passed from C, where any other value is representable.
+    fn from_raw(value: u32) -> Result<Self, Failure> {
+        match value {
+            0 => Ok(TiktokenAllowedSpecial::None),
+            1 => Ok(TiktokenAllowedSpecial::All),
+            2 => Ok(TiktokenAllowedSpecial::Tokens),
+            _ => Err(Failure::new(
+                TiktokenStatus::InvalidArgument,
+                format!("Invalid allowed special tokens: {}", value),
+            )),
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+/// The message of the last error on the calling thread, or null if there was none. The string
+/// stays valid until the next call that fails on the same thread.
+#[no_mangle]
+pub extern "C" fn tiktoken_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Creates an encoding from ranks in the `.tiktoken` text format, `n_special_tokens` special
+/// tokens with their ids, and a split pattern. Parsed ranks are cached, so creating another
+/// encoding from the same ranks is cheap.
+///
+/// # Safety
+///
+/// `ranks` must point to `ranks_len` readable bytes, `special_tokens` and `special_token_ids`
+/// to `n_special_tokens` elements (they may be null if it is zero), and every string must be
+/// nul-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn tiktoken_new(
+    ranks: *const u8,
+    ranks_len: usize,
+    special_tokens: *const *const c_char,
+    special_token_ids: *const u32,
+    n_special_tokens: usize,
+    pattern: *const c_char,
+    encoding: *mut *mut TiktokenEncoding,
+) -> TiktokenStatus {
+    guard(|| {
+        let ranks = utf8(bytes(ranks, ranks_len)?)?;
+        let names = strings(special_tokens, n_special_tokens)?;
+        let ids = array(special_token_ids, n_special_tokens)?;
+        let pattern = c_str(pattern)?;
+
+        let special_tokens = names.into_iter().map(String::from).zip(ids.iter().copied());
+        let created = Encoding::new(ranks, special_tokens, pattern).map_err(Failure::from)?;
+        write(encoding, boxed(created))
+    })
+}
+
+/// Creates one of the encodings compiled into the library, such as `cl100k_base`.
+///
+/// # Safety
+///
+/// `name` must be a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn tiktoken_get_encoding(
+    name: *const c_char,
+    encoding: *mut *mut TiktokenEncoding,
+) -> TiktokenStatus {
+    guard(|| {
+        let name = c_str(name)?;
+        let created = Encoding::get(name).map_err(Failure::from)?;
+        write(encoding, boxed(created))
+    })
+}
+
+/// Creates the encoding used by an OpenAI model, such as `text-embedding-ada-002`.
+///
+/// # Safety
+///
+/// `model` must be a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn tiktoken_encoding_for_model(
+    model: *const c_char,
+    encoding: *mut *mut TiktokenEncoding,
+) -> TiktokenStatus {
+    guard(|| {
+        let model = c_str(model)?;
+        let created = Encoding::for_model(model).map_err(Failure::from)?;
+        write(encoding, boxed(created))
+    })
+}
+
+/// Releases an encoding. Null is ignored.
+///
+/// # Safety
+///
+/// `encoding` must have been created by this library and not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn tiktoken_free(encoding: *mut TiktokenEncoding) {
+    if !encoding.is_null() {
+        drop(Box::from_raw(encoding));
+    }
+}
+
+/// Writes the number of token ids of the encoding, i.e. the largest id plus one.
+///
+/// # Safety
+///
+/// `encoding` must be a live encoding.
+#[no_mangle]
+pub unsafe extern "C" fn tiktoken_n_vocab(
+    encoding: *const TiktokenEncoding,
+    n_vocab: *mut usize,
+) -> TiktokenStatus {
+    guard(|| write(n_vocab, get(encoding)?.n_vocab()))
+}
+
+/// Encodes `text_len` bytes of UTF-8 text into `tokens`, turning the allowed special tokens
+/// into their ids. Text containing any other special token is rejected with
+/// `TIKTOKEN_STATUS_DISALLOWED_SPECIAL_TOKEN`; use `tiktoken_encode_ordinary` to encode it as
+/// ordinary text.
+///
+/// `allowed` is one of the `TiktokenAllowedSpecial` values. With
+/// `TIKTOKEN_ALLOWED_SPECIAL_TOKENS`, every name in `allowed_special` must be a special token
+/// of the encoding, or `TIKTOKEN_STATUS_INVALID_ARGUMENT` is returned.
+///
+/// The number of tokens is written to `n_tokens`. If it exceeds `capacity`, nothing is written
+/// to `tokens` and `TIKTOKEN_STATUS_BUFFER_TOO_SMALL` is returned, so passing a null buffer
+/// queries the size.
+/// `text_len` tokens are always enough.
+///
+/// # Safety
+///
+/// `encoding` must be a live encoding, `text` must point to `text_len` readable bytes,
+/// `allowed_special` to `n_allowed_special` nul-terminated strings when `allowed` is
+/// `TIKTOKEN_ALLOWED_SPECIAL_TOKENS`, and `tokens` to `capacity` writable elements.
+#[no_mangle]
+pub unsafe extern "C" fn tiktoken_encode(
+    encoding: *const TiktokenEncoding,
+    text: *const c_char,
+    text_len: usize,
+    allowed: u32,
+    allowed_special: *const *const c_char,
+    n_allowed_special: usize,
+    tokens: *mut u32,
+    capacity: usize,
+    n_tokens: *mut usize,
+) -> TiktokenStatus {
+    guard(|| {
+        let encoding = get(encoding)?;
+        let text = utf8(bytes(text as *const u8, text_len)?)?;
+        let allowed = TiktokenAllowedSpecial::from_raw(allowed)?;
+        let allowed_tokens = match allowed {
+            TiktokenAllowedSpecial::Tokens => strings(allowed_special, n_allowed_special)?,
+            _ => vec![],
+        };
+        let special_tokens = encoding.special_tokens();
+        if let Some(&token) = allowed_tokens
+            .iter()
+            .find(|&&token| !special_tokens.contains_key(token))
+        {
+            return Err(Failure::from(Error::UnknownSpecialToken {
+                token: String::from(token),
+            }));
+        }
+        let allowed = match allowed {
+            TiktokenAllowedSpecial::None => AllowedSpecial::None,
+            TiktokenAllowedSpecial::All => AllowedSpecial::All,
+            TiktokenAllowedSpecial::Tokens => AllowedSpecial::Tokens(&allowed_tokens),
+        };
+
+        let encoded = encoding
+            .encode(text, allowed, DisallowedSpecial::All)
+            .map_err(Failure::from)?;
+        write_slice(&encoded, tokens, capacity, n_tokens)
+    })
+}
+
+/// Encodes `text_len` bytes of UTF-8 text into `tokens`, treating special tokens as ordinary
+/// text. The buffer works as in `tiktoken_encode`.
+///
+/// # Safety
+///
+/// `encoding` must be a live encoding, `text` must point to `text_len` readable bytes, and
+/// `tokens` to `capacity` writable elements.
+#[no_mangle]
+pub unsafe extern "C" fn tiktoken_encode_ordinary(
+    encoding: *const TiktokenEncoding,
+    text: *const c_char,
+    text_len: usize,
+    tokens: *mut u32,
+    capacity: usize,
+    n_tokens: *mut usize,
+) -> TiktokenStatus {
+    guard(|| {
+        let encoding = get(encoding)?;
+        let text = utf8(bytes(text as *const u8, text_len)?)?;
+        write_slice(&encoding.encode_ordinary(text), tokens, capacity, n_tokens)
+    })
+}
+
+/// Decodes `n_tokens` tokens into `bytes`, which is not nul-terminated and need not be valid
+/// UTF-8. The number of bytes is written to `n_bytes`, and a buffer that is too small works as
+/// in `tiktoken_encode`.
+///
+/// # Safety
+///
+/// `encoding` must be a live encoding, `tokens` must point to `n_tokens` readable elements, and
+/// `bytes` to `capacity` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn tiktoken_decode(
+    encoding: *const TiktokenEncoding,
+    tokens: *const u32,
+    n_tokens: usize,
+    bytes: *mut u8,
+    capacity: usize,
+    n_bytes: *mut usize,
+) -> TiktokenStatus {
+    guard(|| {
+        let encoding = get(encoding)?;
+        let tokens = array(tokens, n_tokens)?;
+        let decoded = encoding.decode_bytes(tokens).map_err(Failure::from)?;
+        write_slice(&decoded, bytes, capacity, n_bytes)
+    })
+}
+
+/// A failed call, reported through [`tiktoken_last_error`].
+struct Failure {
+    status: TiktokenStatus,
+    message: String,
+}
+
+impl Failure {
+    fn new(status: TiktokenStatus, message: impl Into<String>) -> Self {
+        Failure {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<Error> for Failure {
+    fn from(error: Error) -> Self {
+        let status = match error {
+            Error::Construction(_) | Error::UnmergeableToken { .. } => {
+                TiktokenStatus::InvalidEncoding
+            }
+            Error::UnknownEncoding { .. } | Error::UnknownModel { .. } => {
+                TiktokenStatus::UnknownEncoding
+            }
+            Error::UnknownSpecialToken { .. } => TiktokenStatus::InvalidArgument,
+            Error::DisallowedSpecialToken { .. } => TiktokenStatus::DisallowedSpecialToken,
+            Error::NotASingleToken { .. } | Error::UnknownToken { .. } => {
+                TiktokenStatus::UnknownToken
+            }
+        };
+        Failure::new(status, error.to_string())
+    }
+}
+
+/// Runs the body of an exported function, recording its error and keeping panics from unwinding
+/// into the caller.
+fn guard(body: impl FnOnce() -> Result<(), Failure>) -> TiktokenStatus {
+    let failure = match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(())) => return TiktokenStatus::Ok,
+        Ok(Err(failure)) => failure,
+        Err(_) => Failure::new(TiktokenStatus::Internal, "tiktoken panicked"),
+    };
+
+    // Special tokens may contain nul bytes, which would end the C string early
+    let message = CString::new(failure.message.replace('\0', "\\0")).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+    failure.status
+}
+
+fn null_pointer() -> Failure {
+    Failure::new(TiktokenStatus::InvalidArgument, "Unexpected null pointer")
+}
+
+unsafe fn get<'a>(encoding: *const TiktokenEncoding) -> Result<&'a Encoding, Failure> {
+    encoding
+        .as_ref()
+        .map(|encoding| &encoding.encoding)
+        .ok_or_else(null_pointer)
+}
+
+unsafe fn array<'a, T>(data: *const T, len: usize) -> Result<&'a [T], Failure> {
+    match len {
+        0 => Ok(&[]),
+        _ if data.is_null() => Err(null_pointer()),
+        _ => Ok(slice::from_raw_parts(data, len)),
+    }
+}
+
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> Result<&'a [u8], Failure> {
+    array(data, len)
+}
+
+fn utf8(bytes: &[u8]) -> Result<&str, Failure> {
+    str::from_utf8(bytes).map_err(|e| {
+        Failure::new(
+            TiktokenStatus::InvalidArgument,
+            format!("Invalid UTF-8: {}", e),
+        )
+    })
+}
+
+unsafe fn c_str<'a>(string: *const c_char) -> Result<&'a str, Failure> {
+    if string.is_null() {
+        return Err(null_pointer());
+    }
+    utf8(CStr::from_ptr(string).to_bytes())
+}
+
+unsafe fn strings<'a>(strings: *const *const c_char, len: usize) -> Result<Vec<&'a str>, Failure> {
+    array(strings, len)?
+        .iter()
+        .map(|&string| c_str(string))
+        .collect()
+}
+
+unsafe fn write<T>(out: *mut T, value: T) -> Result<(), Failure> {
+    if out.is_null() {
+        return Err(null_pointer());
+    }
+    out.write(value);
+    Ok(())
+}
+
+fn boxed(encoding: Encoding) -> *mut TiktokenEncoding {
+    Box::into_raw(Box::new(TiktokenEncoding { encoding }))
+}
+
+unsafe fn write_slice<T: Copy>(
+    values: &[T],
+    out: *mut T,
+    capacity: usize,
+    len: *mut usize,
+) -> Result<(), Failure> {
+    write(len, values.len())?;
+    if values.len() > capacity {
+        return Err(Failure::new(
+            TiktokenStatus::BufferTooSmall,
+            format!(
+                "The result has {} elements but the buffer only holds {}",
+                values.len(),
+                capacity
+            ),
+        ));
+    }
+    if !values.is_empty() {
+        if out.is_null() {
+            return Err(null_pointer());
+        }
+        ptr::copy_nonoverlapping(values.as_ptr(), out, values.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose, Engine as _};
+    use std::ffi::CStr;
+    use std::ptr;
+
+    use super::*;
+
+    #[test]
+    fn encodes_into_caller_buffers() {
+        fn assert_thread_safe<T: Send + Sync>() {}
+        assert_thread_safe::<TiktokenEncoding>();
+
+        let mut ranks: String = (0..=255u8)
+            .map(|byte| format!("{} {}\n", general_purpose::STANDARD.encode([byte]), byte))
+            .collect();
+        ranks.push_str("YWI= 256\n");
+        let special_tokens = [b"<|x|>\0".as_ptr() as *const c_char];
+        let mut encoding = ptr::null_mut();
+        unsafe {
+            let status = tiktoken_new(
+                ranks.as_ptr(),
+                ranks.len(),
+                special_tokens.as_ptr(),
+                [257].as_ptr(),
+                1,
+                b"\\w+|<\\|x\\|>\0".as_ptr() as *const c_char,
+                &mut encoding,
+            );
+            assert_eq!(status, TiktokenStatus::Ok);
+
+            let text = "ab<|x|>";
+            let mut n_tokens = 0;
+            let status = tiktoken_encode(
+                encoding,
+                text.as_ptr() as *const c_char,
+                text.len(),
+                TiktokenAllowedSpecial::All as u32,
+                ptr::null(),
+                0,
+                ptr::null_mut(),
+                0,
+                &mut n_tokens,
+            );
+            assert_eq!(status, TiktokenStatus::BufferTooSmall);
+            assert_eq!(n_tokens, 2);
+
+            let mut tokens = [0; 2];
+            let status = tiktoken_encode(
+                encoding,
+                text.as_ptr() as *const c_char,
+                text.len(),
+                TiktokenAllowedSpecial::All as u32,
+                ptr::null(),
+                0,
+                tokens.as_mut_ptr(),
+                tokens.len(),
+                &mut n_tokens,
+            );
+            assert_eq!(status, TiktokenStatus::Ok);
+            assert_eq!(tokens, [256, 257]);
+
+            let status = tiktoken_encode(
+                encoding,
+                text.as_ptr() as *const c_char,
+                text.len(),
+                TiktokenAllowedSpecial::None as u32,
+                ptr::null(),
+                0,
+                tokens.as_mut_ptr(),
+                tokens.len(),
+                &mut n_tokens,
+            );
+            assert_eq!(status, TiktokenStatus::DisallowedSpecialToken);
+            assert_eq!(
+                CStr::from_ptr(tiktoken_last_error()).to_str(),
+                Ok("The text contains a special token that is not allowed: <|x|>")
+            );
+
+            let status = tiktoken_encode(
+                encoding,
+                text.as_ptr() as *const c_char,
+                text.len(),
+                7,
+                ptr::null(),
+                0,
+                tokens.as_mut_ptr(),
+                tokens.len(),
+                &mut n_tokens,
+            );
+            assert_eq!(status, TiktokenStatus::InvalidArgument);
+
+            let allowed_special = [b"<|y|>\0".as_ptr() as *const c_char];
+            let status = tiktoken_encode(
+                encoding,
+                text.as_ptr() as *const c_char,
+                text.len(),
+                TiktokenAllowedSpecial::Tokens as u32,
+                allowed_special.as_ptr(),
+                1,
+                tokens.as_mut_ptr(),
+                tokens.len(),
+                &mut n_tokens,
+            );
+            assert_eq!(status, TiktokenStatus::InvalidArgument);
+            assert_eq!(
+                CStr::from_ptr(tiktoken_last_error()).to_str(),
+                Ok("Unknown special token: <|y|>")
+            );
+
+            let mut bytes = [0; 16];
+            let mut n_bytes = 0;
+            let status = tiktoken_decode(
+                encoding,
+                [256, 257, 258].as_ptr(),
+                3,
+                bytes.as_mut_ptr(),
+                bytes.len(),
+                &mut n_bytes,
+            );
+            assert_eq!(status, TiktokenStatus::UnknownToken);
+
+            let status = tiktoken_decode(
+                encoding,
+                tokens.as_ptr(),
+                tokens.len(),
+                bytes.as_mut_ptr(),
+                bytes.len(),
+                &mut n_bytes,
+            );
+            assert_eq!(status, TiktokenStatus::Ok);
+            assert_eq!(&bytes[..n_bytes], text.as_bytes());
+
+            tiktoken_free(encoding);
+        }
+    }
+}
@@ -0,0 +1,502 @@
+//! [`Encoding`], the Rust counterpart of the JavaScript `Tiktoken` class and the Python
+//! `tiktoken.Encoding`.
+
+#[cfg(feature = "embedded")]
+use rustc_hash::FxHashMap as HashMap;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+#[cfg(feature = "embedded")]
+use crate::embedded;
+use crate::{
+    binary, byte_level, cached_vocab, huggingface, models, snapshot, validate_vocabulary,
+    ConstructionError, CoreBPE, CoreBPEConstructor, Error, Rank, ValidationReport, Vocab, VocabKey,
+    ENDOFTEXT,
+};
+
+/// The special tokens that [`Encoding::encode`] turns into their ids. Any others are encoded
+/// as ordinary text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllowedSpecial<'a> {
+    /// No special tokens, the default.
+    None,
+    /// Every special token of the encoding.
+    All,
+    /// The given special tokens.
+    Tokens(&'a [&'a str]),
+}
+
+impl Default for AllowedSpecial<'_> {
+    fn default() -> Self {
+        AllowedSpecial::None
+    }
+}
+
+/// The special tokens that make [`Encoding::encode`] fail when the text contains them, which
+/// guards against user input that would otherwise be encoded as a control token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisallowedSpecial<'a> {
+    /// Every special token that is not allowed, the default.
+    All,
+    /// No special tokens, so the ones that are not allowed are encoded as ordinary text.
+    None,
+    /// The given special tokens.
+    Tokens(&'a [&'a str]),
+}
+
+impl Default for DisallowedSpecial<'_> {
+    fn default() -> Self {
+        DisallowedSpecial::All
+    }
+}
+
+/// A named [`CoreBPE`] with the special token checks of the Python `tiktoken.Encoding`.
+/// Cloning is cheap, and encodings derived with the `with_*` methods share their vocabulary.
+#[derive(Clone)]
+pub struct Encoding {
+    name: Option<String>,
+    bpe: CoreBPE,
+}
+
+impl Encoding {
+    /// Builds an encoding from ranks in the `.tiktoken` text format, special tokens and a split
+    /// pattern. Parsed ranks are cached, so building another encoding from the same ranks is
+    /// cheap. Every byte must have a token of its own, or some text could not be encoded.
+    pub fn new(
+        tiktoken_bfe: &str,
+        special_tokens: impl IntoIterator<Item = (String, Rank)>,
+        pattern: &str,
+    ) -> Result<Self, Error> {
+        let constructor = CoreBPEConstructor::new(
+            tiktoken_bfe,
+            Some(special_tokens.into_iter().collect()),
+            pattern,
+        )?;
+        Encoding::from_constructor(None, constructor)
+    }
+
+    /// One of the encodings compiled into this build, such as `cl100k_base`. Without any
+    /// encoding features, every name is unknown.
+    pub fn get(name: &str) -> Result<Self, Error> {
+        #[cfg(feature = "embedded")]
+        return Encoding::embedded(name, HashMap::default());
+
+        #[cfg(not(feature = "embedded"))]
+        Err(Error::UnknownEncoding {
+            name: String::from(name),
+        })
+    }
+
+    /// The names of the encodings compiled into this build, which [`Encoding::get`] accepts.
+    pub fn names() -> &'static [&'static str] {
+        #[cfg(feature = "embedded")]
+        return embedded::ENCODINGS;
+
+        #[cfg(not(feature = "embedded"))]
+        &[]
+    }
+
+    /// The name of the encoding used by an OpenAI model, such as `cl100k_base` for
+    /// `text-embedding-ada-002`, whether or not that encoding was compiled into this build.
+    pub fn name_for_model(model: &str) -> Option<&'static str> {
+        models::MODEL_TO_ENCODING
+            .iter()
+            .find(|&&(name, _)| name == model)
+            .map(|&(_, encoding)| encoding)
+    }
+
+    /// The encoding used by an OpenAI model, such as `text-embedding-ada-002`, if it was compiled
+    /// into this build.
+    pub fn for_model(model: &str) -> Result<Self, Error> {
+        #[cfg(feature = "embedded")]
+        return Encoding::embedded_for_model(model, HashMap::default());
+
+        #[cfg(not(feature = "embedded"))]
+        Err(Error::UnknownModel {
+            model: String::from(model),
+        })
+    }
+
+    #[cfg(feature = "embedded")]
+    pub(crate) fn embedded(
+        name: &str,
+        extend_special_tokens: HashMap<String, Rank>,
+    ) -> Result<Self, Error> {
+        let mut constructor: CoreBPEConstructor = match name {
+            #[cfg(feature = "gpt2")]
+            "gpt2" => CoreBPEConstructor::gpt2(),
+            #[cfg(feature = "r50k_base")]
+            "r50k_base" => CoreBPEConstructor::r50k_base(),
+            #[cfg(feature = "p50k_base")]
+            "p50k_base" => CoreBPEConstructor::p50k_base(),
+            #[cfg(feature = "p50k_base")]
+            "p50k_edit" => CoreBPEConstructor::p50k_edit(),
+            #[cfg(feature = "cl100k_base")]
+            "cl100k_base" => CoreBPEConstructor::cl100k_base(),
+            &_ => {
+                return Err(Error::UnknownEncoding {
+                    name: String::from(name),
+                })
+            }
+        }?;
+
+        constructor.special_tokens.extend(extend_special_tokens);
+        Encoding::from_constructor(Some(String::from(name)), constructor)
+    }
+
+    #[cfg(feature = "embedded")]
+    pub(crate) fn embedded_for_model(
+        model: &str,
+        extend_special_tokens: HashMap<String, Rank>,
+    ) -> Result<Self, Error> {
+        let encoding = Encoding::name_for_model(model)
+            .filter(|encoding| embedded::ENCODINGS.contains(encoding))
+            .ok_or_else(|| Error::UnknownModel {
+                model: String::from(model),
+            })?;
+
+        Encoding::embedded(encoding, extend_special_tokens)
+    }
+
+    /// Loads an encoding, including its name, pattern and special tokens, from the binary
+    /// vocabulary format written by [`Encoding::to_binary`].
+    pub fn from_binary(data: &[u8]) -> Result<Self, Error> {
+        let encoding = binary::read(data)?;
+        let vocab = cached_vocab(VocabKey::binary(encoding.tokens), encoding.tokens, || {
+            binary::parse_tokens(encoding.tokens, encoding.tokens_offset)
+        })?;
+
+        Ok(Encoding::from_bpe(
+            encoding.name,
+            CoreBPE::from_vocab(vocab, encoding.special_tokens, &encoding.pattern)?,
+        ))
+    }
+
+    /// Converts the byte-level BPE model of a Hugging Face `tokenizer.json`. Added tokens
+    /// marked `special` become special tokens, and the `Split` pre-tokenizer regex becomes the
+    /// pattern. Tokenizers with a normalizer or other processing tiktoken cannot reproduce
+    /// are rejected.
+    pub fn from_huggingface(tokenizer_json: &str) -> Result<Self, Error> {
+        let import = huggingface::import(tokenizer_json)?;
+        Ok(Encoding::from_bpe(
+            None,
+            CoreBPE::from_vocab(
+                Arc::new(import.vocab),
+                import.special_tokens,
+                &import.pattern,
+            )?,
+        ))
+    }
+
+    /// Loads the `encoder.json` and `vocab.bpe` files of the original GPT-2 release, or of a
+    /// model derived from it, with the GPT-2 split pattern. The ids in `encoder.json` must
+    /// increase in the order of the merges in `vocab.bpe`.
+    pub fn from_gpt2(encoder_json: &str, vocab_bpe: &str) -> Result<Self, Error> {
+        let (vocab, special_tokens) = byte_level::import_gpt2(encoder_json, vocab_bpe)?;
+        Ok(Encoding::from_bpe(
+            None,
+            CoreBPE::from_vocab(Arc::new(vocab), special_tokens, byte_level::GPT2_PATTERN)?,
+        ))
+    }
+
+    /// Restores an encoding written by [`Encoding::serialize`], rejecting snapshots that are
+    /// corrupt or come from an incompatible version.
+    pub fn deserialize(data: &[u8]) -> Result<Self, Error> {
+        let snapshot = snapshot::read(data)?;
+        let vocab = cached_vocab(VocabKey::tables(snapshot.tables), snapshot.tables, || {
+            Vocab::from_bytes(std::borrow::Cow::Owned(snapshot.tables.to_vec()))
+                .ok_or(ConstructionError::MalformedSnapshot)
+        })?;
+
+        Ok(Encoding::from_bpe(
+            snapshot.name,
+            CoreBPE::from_vocab(vocab, snapshot.special_tokens, &snapshot.pattern)?,
+        ))
+    }
+
+    fn from_constructor(
+        name: Option<String>,
+        constructor: CoreBPEConstructor,
+    ) -> Result<Self, Error> {
+        Ok(Encoding::from_bpe(
+            name,
+            CoreBPE::from_vocab(
+                constructor.vocab,
+                constructor.special_tokens,
+                &constructor.pat_str,
+            )?,
+        ))
+    }
+
+    pub(crate) fn from_bpe(name: Option<String>, bpe: CoreBPE) -> Self {
+        Encoding { name, bpe }
+    }
+
+    /// Serializes this encoding, so that [`Encoding::deserialize`] can restore it without
+    /// parsing or sorting the vocabulary again.
+    pub fn serialize(&self) -> Vec<u8> {
+        snapshot::write(
+            self.name.as_deref(),
+            &self.bpe.pattern,
+            &self.bpe.special_tokens_encoder,
+            &self.bpe.vocab,
+        )
+    }
+
+    /// Writes this encoding in the binary vocabulary format read by [`Encoding::from_binary`].
+    pub fn to_binary(&self) -> Vec<u8> {
+        binary::write(
+            self.name.as_deref(),
+            &self.bpe.pattern,
+            &self.bpe.special_tokens_encoder,
+            &self.bpe.vocab,
+        )
+    }
+
+    /// Derives an encoding with additional special tokens. Existing tokens may be given a new id.
+    /// The vocabulary and split pattern are shared with this encoding.
+    pub fn with_special_tokens(
+        &self,
+        special_tokens: impl IntoIterator<Item = (String, Rank)>,
+    ) -> Result<Self, Error> {
+        let mut special_tokens_encoder = self.bpe.special_tokens_encoder.clone();
+        special_tokens_encoder.extend(special_tokens);
+
+        Ok(Encoding::from_bpe(
+            self.name.clone(),
+            self.bpe.with_special_tokens(special_tokens_encoder)?,
+        ))
+    }
+
+    /// Derives an encoding without the given special tokens, sharing the vocabulary and split
+    /// pattern with this encoding.
+    pub fn without_special_tokens(&self, tokens: &[&str]) -> Result<Self, Error> {
+        let mut special_tokens_encoder = self.bpe.special_tokens_encoder.clone();
+        for &token in tokens {
+            if special_tokens_encoder.remove(token).is_none() {
+                return Err(Error::UnknownSpecialToken {
+                    token: String::from(token),
+                });
+            }
+        }
+
+        Ok(Encoding::from_bpe(
+            self.name.clone(),
+            self.bpe.with_special_tokens(special_tokens_encoder)?,
+        ))
+    }
+
+    /// Derives an encoding that splits text with `pattern`, sharing the vocabulary and special
+    /// tokens with this encoding.
+    pub fn with_pattern(&self, pattern: &str) -> Result<Self, Error> {
+        Ok(Encoding::from_bpe(
+            self.name.clone(),
+            self.bpe.with_pattern(pattern)?,
+        ))
+    }
+
+    /// Derives an identical encoding under a different name.
+    pub fn with_name(&self, name: &str) -> Self {
+        Encoding::from_bpe(Some(String::from(name)), self.bpe.clone())
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The number of token ids, i.e. `max_token_value + 1`.
+    pub fn n_vocab(&self) -> usize {
+        self.bpe.max_token_value as usize + 1
+    }
+
+    pub fn max_token_value(&self) -> Rank {
+        self.bpe.max_token_value
+    }
+
+    /// The id of `<|endoftext|>`, if the encoding has one.
+    pub fn eot_token(&self) -> Option<Rank> {
+        self.bpe.special_tokens_encoder.get(ENDOFTEXT).copied()
+    }
+
+    /// The special tokens and their ids, ordered by name.
+    pub fn special_tokens(&self) -> BTreeMap<&str, Rank> {
+        self.bpe.special_tokens().collect()
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.bpe.pattern
+    }
+
+    /// The tokeniser behind this encoding.
+    pub fn core(&self) -> &CoreBPE {
+        &self.bpe
+    }
+
+    /// Checks the vocabulary for inconsistencies, optionally against an expected vocabulary size
+    /// like the `explicit_n_vocab` argument of the Python `Encoding`.
+    pub fn validate(&self, explicit_n_vocab: Option<usize>) -> ValidationReport {
+        validate_vocabulary(
+            self.bpe.vocab.iter(),
+            &self.bpe.special_tokens_encoder,
+            explicit_n_vocab,
+        )
+    }
+
+    /// Writes the ordinary tokens in the `.tiktoken` text format, in rank order. The result can
+    /// be loaded by [`Encoding::new`] and by the Python `tiktoken.load.load_tiktoken_bpe`.
+    pub fn export_ranks(&self) -> String {
+        CoreBPEConstructor::export_bfe(&self.bpe.vocab)
+    }
+
+    /// Converts the encoding to a Hugging Face `tokenizer.json` with a byte-level BPE model.
+    /// The merges are derived from the ranks, special tokens become added tokens, and the
+    /// pattern becomes a `Split` pre-tokenizer, which Hugging Face evaluates with Oniguruma.
+    pub fn export_huggingface(&self) -> Result<String, Error> {
+        huggingface::export(
+            &self.bpe.vocab,
+            &self.bpe.special_tokens_encoder,
+            &self.bpe.pattern,
+        )
+    }
+
+    /// The rest of the encoding as JSON, with the keyword arguments of the Python `Encoding`:
+    /// `name`, `pat_str`, `special_tokens` and `explicit_n_vocab`.
+    pub fn export_config(&self) -> String {
+        serde_json::json!({
+            "name": self.name,
+            "pat_str": self.bpe.pattern,
+            "special_tokens": self.special_tokens(),
+            "explicit_n_vocab": self.n_vocab(),
+        })
+        .to_string()
+    }
+
+    /// Encodes `text`, turning the allowed special tokens into their ids. Fails if the text
+    /// contains a disallowed special token.
+    pub fn encode(
+        &self,
+        text: &str,
+        allowed_special: AllowedSpecial,
+        disallowed_special: DisallowedSpecial,
+    ) -> Result<Vec<Rank>, Error> {
+        let allowed_special = self.allowed_special(text, allowed_special, disallowed_special)?;
+        Ok(self.bpe.encode(text, &allowed_special))
+    }
+
+    /// Encodes `text`, treating special tokens as ordinary text.
+    pub fn encode_ordinary(&self, text: &str) -> Vec<Rank> {
+        self.bpe.encode_ordinary(text)
+    }
+
+    /// Like [`Encoding::encode`], but returns the tokens that could merge differently once more
+    /// text is appended separately, see [`CoreBPE::encode_with_unstable`].
+    pub fn encode_with_unstable(
+        &self,
+        text: &str,
+        allowed_special: AllowedSpecial,
+        disallowed_special: DisallowedSpecial,
+    ) -> Result<(Vec<Rank>, HashSet<Vec<Rank>>), Error> {
+        let allowed_special = self.allowed_special(text, allowed_special, disallowed_special)?;
+        Ok(self.bpe.encode_with_unstable(text, &allowed_special))
+    }
+
+    /// The id of the ordinary or special token consisting of exactly `bytes`.
+    pub fn encode_single_token(&self, bytes: &[u8]) -> Result<Rank, Error> {
+        self.bpe.encode_single_token(bytes)
+    }
+
+    /// Decodes `tokens` to bytes, which need not be valid UTF-8 on their own.
+    pub fn decode_bytes(&self, tokens: &[Rank]) -> Result<Vec<u8>, Error> {
+        self.bpe.decode_bytes(tokens)
+    }
+
+    pub fn decode_single_token_bytes(&self, token: Rank) -> Result<Vec<u8>, Error> {
+        self.bpe.decode_single_token_bytes(token)
+    }
+
+    /// The bytes of every ordinary token, in byte order.
+    pub fn token_byte_values(&self) -> Vec<Vec<u8>> {
+        self.bpe.token_byte_values()
+    }
+
+    /// The ordinary tokens and their bytes, in rank order.
+    pub fn ordinary_tokens(&self) -> impl Iterator<Item = (Rank, &[u8])> + '_ {
+        self.bpe.vocab.iter().map(|(bytes, rank)| (rank, bytes))
+    }
+
+    fn allowed_special<'a>(
+        &'a self,
+        text: &str,
+        allowed_special: AllowedSpecial<'a>,
+        disallowed_special: DisallowedSpecial,
+    ) -> Result<HashSet<&'a str>, Error> {
+        let special_tokens = self.bpe.special_tokens_encoder.keys().map(String::as_str);
+        let allowed_special: HashSet<&str> = match allowed_special {
+            AllowedSpecial::None => HashSet::new(),
+            AllowedSpecial::All => special_tokens.clone().collect(),
+            AllowedSpecial::Tokens(tokens) => tokens.iter().copied().collect(),
+        };
+
+        let disallowed_special: Vec<&str> = match disallowed_special {
+            DisallowedSpecial::All => special_tokens
+                .filter(|token| !allowed_special.contains(token))
+                .collect(),
+            DisallowedSpecial::None => vec![],
+            DisallowedSpecial::Tokens(tokens) => tokens.to_vec(),
+        };
+
+        if !disallowed_special.is_empty() {
+            if let Some(found) = special_token_regex(&disallowed_special).find(text) {
+                return Err(Error::DisallowedSpecialToken {
+                    token: String::from(found.as_str()),
+                });
+            }
+        }
+
+        Ok(allowed_special)
+    }
+}
+
+/// The `allowed_special` and `disallowed_special` arguments of the encode methods in the
+/// JavaScript bindings, either `"all"` (stored as `None`) or a list of special tokens.
+#[cfg(any(feature = "wasm", feature = "node"))]
+pub(crate) struct SpecialParams {
+    pub(crate) allowed: Option<Vec<String>>,
+    pub(crate) disallowed: Option<Vec<String>>,
+}
+
+#[cfg(any(feature = "wasm", feature = "node"))]
+impl SpecialParams {
+    pub(crate) fn apply<T>(
+        &self,
+        encode: impl FnOnce(AllowedSpecial, DisallowedSpecial) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let allowed: Option<Vec<&str>> = self
+            .allowed
+            .as_ref()
+            .map(|tokens| tokens.iter().map(String::as_str).collect());
+        let disallowed: Option<Vec<&str>> = self
+            .disallowed
+            .as_ref()
+            .map(|tokens| tokens.iter().map(String::as_str).collect());
+
+        encode(
+            allowed
+                .as_deref()
+                .map_or(AllowedSpecial::All, AllowedSpecial::Tokens),
+            disallowed
+                .as_deref()
+                .map_or(DisallowedSpecial::All, DisallowedSpecial::Tokens),
+        )
+    }
+}
+
+fn special_token_regex(tokens: &[&str]) -> regex::Regex {
+    let inner = tokens
+        .iter()
+        .map(|token| regex::escape(token))
+        .collect::<Vec<String>>()
+        .join("|");
+
+    regex::Regex::new(&format!("({})", inner)).expect("escaped special tokens are a valid regex")
+}
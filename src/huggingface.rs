@@ -0,0 +1,349 @@
+//! Conversion between encodings and the byte-level BPE models of Hugging Face `tokenizer.json`
+//! files.
+//!
+//! Only the parts of the format that tiktoken can reproduce exactly are accepted: no
+//! normalizer, a `ByteLevel` pre-tokenizer (optionally after a regex `Split`) and a BPE model
+//! without subword affixes. Anything else is rejected rather than silently tokenized
+//! differently. Exported files use exactly that subset.
+
+use rustc_hash::FxHashMap as HashMap;
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::json;
+use std::collections::HashSet;
+
+use crate::byte_level::{self, vocab_from_merges, GPT2_PATTERN};
+use crate::vocab::{Rank, Ranks, Vocab};
+use crate::{_byte_pair_merge, ConstructionError, Error};
+
+#[derive(Deserialize)]
+struct TokenizerJson {
+    #[serde(default)]
+    added_tokens: Vec<AddedToken>,
+    normalizer: Option<serde_json::Value>,
+    pre_tokenizer: Option<serde_json::Value>,
+    model: Model,
+}
+
+#[derive(Deserialize)]
+struct AddedToken {
+    id: u32,
+    content: String,
+    #[serde(default)]
+    special: bool,
+}
+
+#[derive(Deserialize)]
+struct Model {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    vocab: HashMap<String, u32>,
+    merges: Vec<Merge>,
+    continuing_subword_prefix: Option<String>,
+    end_of_word_suffix: Option<String>,
+}
+
+/// Merges are written as `"left right"`, or as `["left", "right"]` by newer versions.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Merge {
+    Joined(String),
+    Pair(String, String),
+}
+
+/// The inputs of an encoding converted from a `tokenizer.json`.
+pub(crate) struct Import {
+    pub(crate) vocab: Vocab,
+    pub(crate) special_tokens: HashMap<String, Rank>,
+    pub(crate) pattern: String,
+}
+
+fn unsupported(message: String) -> ConstructionError {
+    ConstructionError::UnsupportedTokenizer { message }
+}
+
+fn kind(value: &serde_json::Value) -> &str {
+    value["type"].as_str().unwrap_or("without a type")
+}
+
+/// The split pattern of a supported pre-tokenizer.
+fn pre_tokenizer_pattern(pre_tokenizer: &serde_json::Value) -> Result<String, ConstructionError> {
+    let byte_level = |value: &serde_json::Value, use_regex: bool| {
+        if kind(value) != "ByteLevel" {
+            return Err(unsupported(format!("pre_tokenizer {}", kind(value))));
+        }
+        if value["add_prefix_space"].as_bool() == Some(true) {
+            return Err(unsupported(String::from(
+                "ByteLevel pre_tokenizer with add_prefix_space",
+            )));
+        }
+        if value["use_regex"].as_bool().unwrap_or(true) != use_regex {
+            return Err(unsupported(format!(
+                "ByteLevel pre_tokenizer with use_regex {}",
+                !use_regex
+            )));
+        }
+        Ok(())
+    };
+
+    let steps = match pre_tokenizer["pretokenizers"].as_array() {
+        Some(steps) if kind(pre_tokenizer) == "Sequence" => steps.as_slice(),
+        _ => std::slice::from_ref(pre_tokenizer),
+    };
+    match steps {
+        [byte_level_step] => {
+            // `use_regex` splits with the pattern of the original GPT-2 encoder
+            byte_level(byte_level_step, true)?;
+            Ok(String::from(GPT2_PATTERN))
+        }
+        [split, byte_level_step] if kind(split) == "Split" => {
+            if split["behavior"].as_str() != Some("Isolated") || split["invert"] == true {
+                return Err(unsupported(String::from(
+                    "Split pre_tokenizer that does not isolate its matches",
+                )));
+            }
+            byte_level(byte_level_step, false)?;
+            match (
+                split["pattern"]["Regex"].as_str(),
+                split["pattern"]["String"].as_str(),
+            ) {
+                (Some(regex), _) => Ok(String::from(regex)),
+                (None, Some(string)) => Ok(fancy_regex::escape(string).into_owned()),
+                (None, None) => Err(ConstructionError::InvalidTokenizer {
+                    message: String::from("Split pre_tokenizer without a pattern"),
+                }),
+            }
+        }
+        _ => Err(unsupported(format!(
+            "pre_tokenizer sequence [{}]",
+            steps.iter().map(kind).collect::<Vec<_>>().join(", ")
+        ))),
+    }
+}
+
+pub(crate) fn import(tokenizer_json: &str) -> Result<Import, ConstructionError> {
+    let tokenizer: TokenizerJson =
+        serde_json::from_str(tokenizer_json).map_err(|e| ConstructionError::InvalidTokenizer {
+            message: e.to_string(),
+        })?;
+
+    if let Some(normalizer) = &tokenizer.normalizer {
+        return Err(ConstructionError::UnsupportedNormalizer {
+            normalizer: String::from(kind(normalizer)),
+        });
+    }
+    let pattern = match &tokenizer.pre_tokenizer {
+        Some(pre_tokenizer) => pre_tokenizer_pattern(pre_tokenizer)?,
+        None => {
+            return Err(unsupported(String::from(
+                "tokenizer without a pre_tokenizer",
+            )))
+        }
+    };
+
+    let model = tokenizer.model;
+    match model.kind.as_deref() {
+        None | Some("BPE") => {}
+        Some(kind) => return Err(unsupported(format!("model {}", kind))),
+    }
+    if model.continuing_subword_prefix.is_some() || model.end_of_word_suffix.is_some() {
+        return Err(unsupported(String::from(
+            "BPE model with subword prefixes or suffixes",
+        )));
+    }
+
+    // Added tokens that are not special can only be imported as the model tokens they already
+    // are, since tiktoken has no other tokens that are matched outside the pattern
+    if let Some(token) = tokenizer
+        .added_tokens
+        .iter()
+        .find(|token| !token.special && model.vocab.get(&token.content) != Some(&token.id))
+    {
+        return Err(unsupported(format!(
+            "added token {:?} that is neither special nor in the model vocabulary",
+            token.content
+        )));
+    }
+
+    let special_tokens: HashMap<String, Rank> = tokenizer
+        .added_tokens
+        .into_iter()
+        .filter(|token| token.special)
+        .map(|token| (token.content, token.id))
+        .collect();
+
+    // Special tokens are often part of the model vocabulary as well
+    let special_ids: HashSet<Rank> = special_tokens.values().copied().collect();
+    let mut tokens = model.vocab;
+    tokens.retain(|_, id| !special_ids.contains(id));
+
+    let mut merges = Vec::with_capacity(model.merges.len());
+    for (index, merge) in model.merges.iter().enumerate() {
+        merges.push(match merge {
+            Merge::Pair(left, right) => (left.as_str(), right.as_str()),
+            Merge::Joined(merge) => match merge.split_once(' ') {
+                Some(pair) => pair,
+                None => {
+                    return Err(ConstructionError::InvalidTokenizer {
+                        message: format!("merge {} is not a pair: {:?}", index + 1, merge),
+                    })
+                }
+            },
+        });
+    }
+
+    Ok(Import {
+        vocab: vocab_from_merges(&tokens, merges)?,
+        special_tokens,
+        pattern,
+    })
+}
+
+/// The ranks below `limit`, so that merging stops just before a token is formed.
+struct RanksBelow<'a> {
+    vocab: &'a Vocab,
+    limit: Rank,
+}
+
+impl Ranks for RanksBelow<'_> {
+    fn rank(&self, token: &[u8]) -> Option<Rank> {
+        self.vocab.rank(token).filter(|&rank| rank < self.limit)
+    }
+
+    fn merged_rank(&self, left: Rank, right: Rank, merged: &[u8]) -> Option<Rank> {
+        self.vocab
+            .merged_rank(left, right, merged)
+            .filter(|&rank| rank < self.limit)
+    }
+}
+
+/// Serializes the vocabulary as a map in rank order, rather than the order of a `HashMap`. It
+/// must be serialized directly: converting it to a `serde_json::Value` would sort the keys.
+struct ByteLevelVocab<'a>(&'a Vocab);
+
+impl Serialize for ByteLevelVocab<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (token, rank) in self.0.iter() {
+            map.serialize_entry(&byte_level::encode(token), &rank)?;
+        }
+        map.end()
+    }
+}
+
+/// An exported `tokenizer.json`, with its fields in the order Hugging Face writes them.
+#[derive(Serialize)]
+struct ExportedTokenizer<'a> {
+    version: &'static str,
+    truncation: Option<()>,
+    padding: Option<()>,
+    added_tokens: Vec<serde_json::Value>,
+    normalizer: Option<()>,
+    pre_tokenizer: serde_json::Value,
+    post_processor: Option<()>,
+    decoder: serde_json::Value,
+    model: ExportedModel<'a>,
+}
+
+#[derive(Serialize)]
+struct ExportedModel<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    dropout: Option<()>,
+    unk_token: Option<()>,
+    continuing_subword_prefix: Option<()>,
+    end_of_word_suffix: Option<()>,
+    fuse_unk: bool,
+    byte_fallback: bool,
+    vocab: ByteLevelVocab<'a>,
+    merges: Vec<String>,
+}
+
+/// The merges that build each token from the pair it splits into when merging is stopped
+/// just before its own rank, in rank order.
+fn merges(vocab: &Vocab) -> Result<Vec<String>, Error> {
+    let mut merges = vec![];
+    for (token, rank) in vocab.iter() {
+        if token.len() < 2 {
+            continue;
+        }
+        let below = RanksBelow { vocab, limit: rank };
+        // Single bytes are parts even when they are not tokens themselves
+        match _byte_pair_merge(token, &below).as_slice() {
+            [(left, Some(_)), (right, Some(_))] => merges.push(format!(
+                "{} {}",
+                byte_level::encode(&token[left.clone()]),
+                byte_level::encode(&token[right.clone()])
+            )),
+            _ => return Err(Error::UnmergeableToken { rank }),
+        }
+    }
+    Ok(merges)
+}
+
+pub(crate) fn export(
+    vocab: &Vocab,
+    special_tokens: &HashMap<String, Rank>,
+    pattern: &str,
+) -> Result<String, Error> {
+    let mut special_tokens: Vec<_> = special_tokens.iter().collect();
+    special_tokens.sort_by_key(|&(_, &id)| id);
+    let added_tokens: Vec<_> = special_tokens
+        .into_iter()
+        .map(|(token, id)| {
+            json!({
+                "id": id,
+                "content": token,
+                "single_word": false,
+                "lstrip": false,
+                "rstrip": false,
+                "normalized": false,
+                "special": true,
+            })
+        })
+        .collect();
+
+    let tokenizer = ExportedTokenizer {
+        version: "1.0",
+        truncation: None,
+        padding: None,
+        added_tokens,
+        normalizer: None,
+        pre_tokenizer: json!({
+            "type": "Sequence",
+            "pretokenizers": [
+                {
+                    "type": "Split",
+                    "pattern": { "Regex": pattern },
+                    "behavior": "Isolated",
+                    "invert": false,
+                },
+                {
+                    "type": "ByteLevel",
+                    "add_prefix_space": false,
+                    "trim_offsets": true,
+                    "use_regex": false,
+                },
+            ],
+        }),
+        post_processor: None,
+        decoder: json!({
+            "type": "ByteLevel",
+            "add_prefix_space": true,
+            "trim_offsets": true,
+            "use_regex": true,
+        }),
+        model: ExportedModel {
+            kind: "BPE",
+            dropout: None,
+            unk_token: None,
+            continuing_subword_prefix: None,
+            end_of_word_suffix: None,
+            fuse_unk: false,
+            byte_fallback: false,
+            vocab: ByteLevelVocab(vocab),
+            merges: merges(vocab)?,
+        },
+    };
+    Ok(serde_json::to_string(&tokenizer).unwrap())
+}
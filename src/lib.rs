@@ -1,111 +1,868 @@
-use anyhow::{anyhow, Error};
+//! A [BPE](https://en.wikipedia.org/wiki/Byte_pair_encoding) tokeniser for use with OpenAI's
+//! models, with the same encodings and behaviour as the Python `tiktoken`.
+//!
+//! The `wasm` feature, enabled by default, adds the JavaScript bindings published to NPM.
+//! Without it, this is a plain Rust library built around [`Encoding`]:
+//!
+//! ```
+//! # #[cfg(feature = "cl100k_base")]
+//! # fn main() -> Result<(), _tiktoken::Error> {
+//! use _tiktoken::{AllowedSpecial, DisallowedSpecial, Encoding};
+//!
+//! let enc = Encoding::get("cl100k_base")?;
+//! let tokens = enc.encode_ordinary("hello world");
+//! assert_eq!(enc.decode_bytes(&tokens)?, b"hello world");
+//!
+//! let tokens = enc.encode("<|endoftext|>", AllowedSpecial::All, DisallowedSpecial::All)?;
+//! assert_eq!(tokens, [100257]);
+//! assert!(enc
+//!     .encode("<|endoftext|>", AllowedSpecial::None, DisallowedSpecial::All)
+//!     .is_err());
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "cl100k_base"))]
+//! # fn main() {}
+//! ```
+
 use base64::{engine::general_purpose, Engine as _};
 use fancy_regex::Regex;
-use gloo_utils::format::JsValueSerdeExt;
+use once_cell::sync::Lazy;
 use rustc_hash::FxHashMap as HashMap;
-use std::collections::HashSet;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BinaryHeap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::result::Result;
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
-#[cfg(feature = "inline")]
-const ENDOFTEXT: &'static str = "<|endoftext|>";
+mod binary;
+mod byte_level;
+#[cfg(feature = "capi")]
+mod capi;
+mod encoding;
+mod huggingface;
+mod models;
+#[cfg(feature = "node")]
+mod node;
+#[cfg(feature = "python")]
+mod python;
+mod snapshot;
+mod vocab;
+#[cfg(feature = "wasi")]
+mod wasi;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+#[cfg(feature = "embedded")]
+mod embedded {
+    include!(concat!(env!("OUT_DIR"), "/embedded.rs"));
+}
 
-#[cfg(feature = "inline")]
-const FIM_PREFIX: &'static str = "<|fim_prefix|>";
+pub use encoding::{AllowedSpecial, DisallowedSpecial, Encoding};
+use vocab::{BuildError, Vocab, VocabBuilder};
+pub use vocab::{Rank, Ranks};
+#[cfg(feature = "wasm")]
+pub use wasm::*;
+
+const ENDOFTEXT: &str = "<|endoftext|>";
+
+#[cfg(any(feature = "p50k_base", feature = "cl100k_base"))]
+const FIM_PREFIX: &str = "<|fim_prefix|>";
+
+#[cfg(any(feature = "p50k_base", feature = "cl100k_base"))]
+const FIM_MIDDLE: &str = "<|fim_middle|>";
+
+#[cfg(any(feature = "p50k_base", feature = "cl100k_base"))]
+const FIM_SUFFIX: &str = "<|fim_suffix|>";
+
+#[cfg(feature = "cl100k_base")]
+const ENDOFPROMPT: &str = "<|endofprompt|>";
+
+/// Reasons why an encoding could not be built from the supplied ranks and pattern.
+///
+/// Line numbers are 1-based and refer to the `.tiktoken` text passed to the constructor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConstructionError {
+    /// A line is not of the form `<base64 token> <rank>`.
+    MalformedLine { line: usize },
+    /// The token column of a line is not valid base64.
+    InvalidBase64 { line: usize, message: String },
+    /// The rank column of a line is not a non-negative integer.
+    InvalidRank { line: usize, rank: String },
+    /// The same rank was already assigned to a token on an earlier line.
+    DuplicateRank { line: usize, rank: Rank },
+    /// The special token map is not an object (or `undefined`).
+    SpecialTokensNotAnObject,
+    /// These special tokens have an empty name or an id that is not an integer in `0..u32::MAX`,
+    /// so that `n_vocab` fits in a `u32`.
+    InvalidSpecialTokens { tokens: Vec<String> },
+    /// A special token was given the rank of an ordinary token.
+    SpecialTokenRankCollision { token: String, rank: Rank },
+    /// Two special tokens were given the same id.
+    DuplicateSpecialTokenId {
+        first: String,
+        second: String,
+        rank: Rank,
+    },
+    /// The vocabulary failed validation; see [`Encoding::validate`].
+    InvalidVocabulary { issues: Vec<ValidationIssue> },
+    /// The largest rank is `u32::MAX` or far exceeds the number of ordinary tokens, which the
+    /// rank-indexed tables cannot hold.
+    RankOutOfRange { rank: Rank, n_tokens: usize },
+    /// A binary vocabulary is truncated or inconsistent at this byte offset.
+    MalformedBinary { offset: usize },
+    /// A binary vocabulary was written in a format version this build cannot read.
+    UnsupportedBinaryVersion { version: u32 },
+    /// A `tokenizer.json` is not valid JSON or lacks required fields.
+    InvalidTokenizer { message: String },
+    /// A `tokenizer.json` has a normalizer, which tiktoken cannot apply.
+    UnsupportedNormalizer { normalizer: String },
+    /// A `tokenizer.json` uses a pre-tokenizer or model that tiktoken cannot reproduce.
+    UnsupportedTokenizer { message: String },
+    /// A GPT-2 `encoder.json` is not a JSON object mapping tokens to ids.
+    InvalidEncoderJson { message: String },
+    /// A line of a GPT-2 `vocab.bpe` is not of the form `<left> <right>`.
+    MalformedMerge { line: usize },
+    /// A token contains characters outside the GPT-2 byte-level alphabet.
+    InvalidByteLevelToken { token: String },
+    /// A merge (1-based) refers to, or produces, a token that is not in the vocabulary.
+    UnknownMergeToken { merge: usize, token: String },
+    /// A merge (1-based) produces a token whose id is not above the previous merge's, so the
+    /// ids cannot serve as merge ranks.
+    MergeOrderMismatch {
+        merge: usize,
+        token: String,
+        id: Rank,
+    },
+    /// A snapshot is truncated or inconsistent.
+    MalformedSnapshot,
+    /// A snapshot was written in a format version this build cannot read.
+    UnsupportedSnapshotVersion { version: u32 },
+    /// A snapshot does not match its checksum, so it was truncated or corrupted after being
+    /// written.
+    SnapshotChecksumMismatch,
+    /// The split pattern failed to compile. `position` is the byte offset into the pattern
+    /// where parsing stopped, when the regex engine reports one.
+    InvalidPattern {
+        position: Option<usize>,
+        message: String,
+    },
+}
 
-#[cfg(feature = "inline")]
-const FIM_MIDDLE: &'static str = "<|fim_middle|>";
+impl ConstructionError {
+    fn invalid_pattern(error: fancy_regex::Error) -> Self {
+        match error {
+            fancy_regex::Error::ParseError(position, inner) => ConstructionError::InvalidPattern {
+                position: Some(position),
+                message: inner.to_string(),
+            },
+            error => ConstructionError::InvalidPattern {
+                position: None,
+                message: error.to_string(),
+            },
+        }
+    }
+}
 
-#[cfg(feature = "inline")]
-const FIM_SUFFIX: &'static str = "<|fim_suffix|>";
+impl fmt::Display for ConstructionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstructionError::MalformedLine { line } => {
+                write!(f, "Malformed line {}: expected `<base64 token> <rank>`", line)
+            }
+            ConstructionError::InvalidBase64 { line, message } => {
+                write!(f, "Invalid base64 token on line {}: {}", line, message)
+            }
+            ConstructionError::InvalidRank { line, rank } => {
+                write!(f, "Invalid rank on line {}: {:?}", line, rank)
+            }
+            ConstructionError::DuplicateRank { line, rank } => {
+                write!(f, "Duplicate rank on line {}: {}", line, rank)
+            }
+            ConstructionError::SpecialTokensNotAnObject => write!(
+                f,
+                "Invalid special tokens: expected an object mapping token strings to ids"
+            ),
+            ConstructionError::InvalidSpecialTokens { tokens } => write!(
+                f,
+                "Invalid special tokens: {} must have non-empty names and integer ids between 0 and {}",
+                tokens
+                    .iter()
+                    .map(|token| format!("{:?}", token))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                u32::MAX - 1
+            ),
+            ConstructionError::SpecialTokenRankCollision { token, rank } => write!(
+                f,
+                "Special token {:?} collides with the ordinary token of rank {}",
+                token, rank
+            ),
+            ConstructionError::DuplicateSpecialTokenId {
+                first,
+                second,
+                rank,
+            } => write!(
+                f,
+                "Special tokens {:?} and {:?} share the same id {}",
+                first, second, rank
+            ),
+            ConstructionError::InvalidVocabulary { issues } => write!(
+                f,
+                "Invalid vocabulary: {}",
+                issues
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            ),
+            ConstructionError::RankOutOfRange { rank, n_tokens } => write!(
+                f,
+                "Rank {} is out of range for a vocabulary of {} tokens",
+                rank, n_tokens
+            ),
+            ConstructionError::MalformedBinary { offset } => {
+                write!(f, "Malformed binary vocabulary at byte {}", offset)
+            }
+            ConstructionError::UnsupportedBinaryVersion { version } => write!(
+                f,
+                "Unsupported binary vocabulary version {} (expected {})",
+                version,
+                binary::VERSION
+            ),
+            ConstructionError::InvalidTokenizer { message } => {
+                write!(f, "Invalid tokenizer.json: {}", message)
+            }
+            ConstructionError::UnsupportedNormalizer { normalizer } => write!(
+                f,
+                "Unsupported normalizer {}: only tokenizers without a normalizer can be imported",
+                normalizer
+            ),
+            ConstructionError::UnsupportedTokenizer { message } => {
+                write!(f, "Unsupported tokenizer.json: {}", message)
+            }
+            ConstructionError::InvalidEncoderJson { message } => {
+                write!(f, "Invalid encoder.json: {}", message)
+            }
+            ConstructionError::MalformedMerge { line } => {
+                write!(f, "Malformed merge on line {}: expected `<left> <right>`", line)
+            }
+            ConstructionError::InvalidByteLevelToken { token } => write!(
+                f,
+                "Token {:?} contains characters outside the byte-level alphabet",
+                token
+            ),
+            ConstructionError::UnknownMergeToken { merge, token } => write!(
+                f,
+                "Merge {} refers to {:?}, which is not in the vocabulary",
+                merge, token
+            ),
+            ConstructionError::MergeOrderMismatch { merge, token, id } => write!(
+                f,
+                "Merge {} produces {:?} with id {}, which is not above the id of the previous merge",
+                merge, token, id
+            ),
+            ConstructionError::MalformedSnapshot => write!(f, "Malformed snapshot"),
+            ConstructionError::UnsupportedSnapshotVersion { version } => write!(
+                f,
+                "Unsupported snapshot version {} (expected {})",
+                version,
+                snapshot::VERSION
+            ),
+            ConstructionError::SnapshotChecksumMismatch => {
+                write!(f, "Snapshot checksum mismatch, the data is corrupt")
+            }
+            ConstructionError::InvalidPattern {
+                position: Some(position),
+                message,
+            } => write!(f, "Invalid pattern at position {}: {}", position, message),
+            ConstructionError::InvalidPattern {
+                position: None,
+                message,
+            } => write!(f, "Invalid pattern: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ConstructionError {}
+
+/// Errors returned by [`Encoding`] and [`CoreBPE`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The encoding could not be built.
+    Construction(ConstructionError),
+    /// No encoding of this name is compiled in.
+    UnknownEncoding { name: String },
+    /// The model is unknown, or its encoding is not compiled in.
+    UnknownModel { model: String },
+    /// The encoding has no special token of this name.
+    UnknownSpecialToken { token: String },
+    /// The text contains a special token that is disallowed.
+    DisallowedSpecialToken { token: String },
+    /// The bytes are neither an ordinary nor a special token.
+    NotASingleToken { bytes: Vec<u8> },
+    /// The id is neither an ordinary nor a special token.
+    UnknownToken { token: Rank },
+    /// An ordinary token is not the merge of two tokens of lower rank, so the encoding cannot
+    /// be expressed as a list of merges.
+    UnmergeableToken { rank: Rank },
+}
 
-#[cfg(feature = "inline")]
-const ENDOFPROMPT: &'static str = "<|endofprompt|>";
+impl From<ConstructionError> for Error {
+    fn from(error: ConstructionError) -> Self {
+        Error::Construction(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Construction(error) => error.fmt(f),
+            Error::UnknownEncoding { name } => write!(f, "Invalid encoding: {}", name),
+            Error::UnknownModel { model } => write!(f, "Invalid model: {}", model),
+            Error::UnknownSpecialToken { token } => {
+                write!(f, "Unknown special token: {}", token)
+            }
+            Error::DisallowedSpecialToken { token } => write!(
+                f,
+                "The text contains a special token that is not allowed: {}",
+                token
+            ),
+            Error::NotASingleToken { bytes } => {
+                write!(f, "Unable to encode single token: {:?}", bytes)
+            }
+            Error::UnknownToken { token } => {
+                write!(f, "Token not found in the vocabulary: {}", token)
+            }
+            Error::UnmergeableToken { rank } => write!(
+                f,
+                "Token {} is not the merge of two tokens of lower rank, so it cannot be exported",
+                rank
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Construction(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// A single problem found by [`Encoding::validate`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ValidationIssue {
+    /// Several ordinary tokens share the same rank.
+    DuplicateRank { rank: Rank, count: usize },
+    /// No ordinary or special token uses the ids in `start..end`.
+    RankGap { start: Rank, end: Rank },
+    /// A special token was given the rank of an ordinary token.
+    SpecialTokenRankCollision { token: String, rank: Rank },
+    /// Two special tokens were given the same id.
+    DuplicateSpecialTokenId {
+        first: String,
+        second: String,
+        rank: Rank,
+    },
+    /// These bytes have no token of their own, so pieces containing them cannot be encoded.
+    MissingSingleByteTokens { bytes: Vec<u8> },
+    /// The number of ordinary and special tokens differs from the expected vocabulary size.
+    UnexpectedTokenCount { expected: usize, actual: usize },
+    /// The largest token id is not `explicit_n_vocab - 1`.
+    UnexpectedMaxTokenValue { expected: Rank, actual: Rank },
+}
+
+impl ValidationIssue {
+    /// Rank gaps are legitimate (cl100k_base has several), everything else breaks an invariant
+    /// that encoding or decoding relies on.
+    pub fn is_error(&self) -> bool {
+        !matches!(self, ValidationIssue::RankGap { .. })
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationIssue::DuplicateRank { rank, count } => {
+                write!(f, "rank {} is used by {} tokens", rank, count)
+            }
+            ValidationIssue::RankGap { start, end } => {
+                write!(f, "ids {}..{} are not assigned to any token", start, end)
+            }
+            ValidationIssue::SpecialTokenRankCollision { token, rank } => write!(
+                f,
+                "special token {:?} collides with the ordinary token of rank {}",
+                token, rank
+            ),
+            ValidationIssue::DuplicateSpecialTokenId {
+                first,
+                second,
+                rank,
+            } => write!(
+                f,
+                "special tokens {:?} and {:?} share the same id {}",
+                first, second, rank
+            ),
+            ValidationIssue::MissingSingleByteTokens { bytes } => {
+                write!(f, "bytes {:?} have no single-byte token", bytes)
+            }
+            ValidationIssue::UnexpectedTokenCount { expected, actual } => {
+                write!(f, "expected {} tokens in total, found {}", expected, actual)
+            }
+            ValidationIssue::UnexpectedMaxTokenValue { expected, actual } => write!(
+                f,
+                "expected the largest token id to be {}, found {}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+/// Summary of an encoding's vocabulary, modeled on the checks done by the Python
+/// `Encoding.__init__`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValidationReport {
+    /// Number of ordinary plus special tokens.
+    pub n_tokens: usize,
+    /// The largest ordinary or special token id.
+    pub max_token_value: Rank,
+    /// `max_token_value + 1`, the size of a logit vector covering every id.
+    pub n_vocab: usize,
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        !self.issues.iter().any(ValidationIssue::is_error)
+    }
+}
+
+/// Identifies a parsed vocabulary in [`VOCAB_CACHE`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum VocabKey {
+    /// One of the embedded rank files.
+    #[cfg(feature = "embedded")]
+    Named(&'static str),
+    /// Ranks supplied at runtime, identified by their length and hash.
+    Content { len: usize, hash: u64 },
+    /// The token section of a binary vocabulary, identified by its length and hash.
+    Binary { len: usize, hash: u64 },
+    /// The tables of a snapshot, identified by their length and hash.
+    Tables { len: usize, hash: u64 },
+}
+
+impl VocabKey {
+    fn content(tiktoken_bfe: &str) -> Self {
+        let mut hasher = DefaultHasher::new();
+        tiktoken_bfe.hash(&mut hasher);
+        VocabKey::Content {
+            len: tiktoken_bfe.len(),
+            hash: hasher.finish(),
+        }
+    }
+
+    fn binary(tokens: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        tokens.hash(&mut hasher);
+        VocabKey::Binary {
+            len: tokens.len(),
+            hash: hasher.finish(),
+        }
+    }
+
+    fn tables(tables: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        tables.hash(&mut hasher);
+        VocabKey::Tables {
+            len: tables.len(),
+            hash: hasher.finish(),
+        }
+    }
+}
+
+/// A vocabulary in [`VOCAB_CACHE`], with the bytes it was loaded from.
+struct CachedVocab {
+    /// Compared on every hit, since different sources can share a [`VocabKey`]. Empty for
+    /// named vocabularies, whose key identifies them.
+    source: Box<[u8]>,
+    vocab: Arc<Vocab>,
+}
+
+/// Parsed vocabularies shared by every [`Encoding`] built from the same ranks, so repeated
+/// [`Encoding::get`] calls don't parse and invert the rank file again.
+static VOCAB_CACHE: Lazy<Mutex<HashMap<VocabKey, CachedVocab>>> = Lazy::new(Default::default);
+
+/// Loads the vocabulary `key` was derived from `source`, or reuses the one cached for the same
+/// source. A source whose key collides with another's is loaded but not cached.
+fn cached_vocab(
+    key: VocabKey,
+    source: &[u8],
+    load: impl FnOnce() -> Result<Vocab, ConstructionError>,
+) -> Result<Arc<Vocab>, ConstructionError> {
+    let mut cache = VOCAB_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    let collides = match cache.get(&key) {
+        Some(cached) if *cached.source == *source => return Ok(cached.vocab.clone()),
+        Some(_) => true,
+        None => false,
+    };
+
+    let vocab = Arc::new(load()?);
+    if !collides {
+        cache.insert(
+            key,
+            CachedVocab {
+                source: source.into(),
+                vocab: vocab.clone(),
+            },
+        );
+    }
+    Ok(vocab)
+}
+
+/// Drops every cached vocabulary. Existing encodings keep working, but the next
+/// [`Encoding::get`] or [`Encoding::new`] call parses its ranks again.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn clear_encoding_cache() {
+    VOCAB_CACHE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clear();
+}
+
+fn validate_vocabulary<'a>(
+    tokens: impl IntoIterator<Item = (&'a [u8], Rank)>,
+    special_tokens: &HashMap<String, Rank>,
+    explicit_n_vocab: Option<usize>,
+) -> ValidationReport {
+    let mut issues = vec![];
+
+    let mut n_ordinary = 0;
+    let mut single_bytes = [false; 256];
+    let mut rank_counts: HashMap<Rank, usize> = HashMap::default();
+    for (token, rank) in tokens {
+        n_ordinary += 1;
+        if let [byte] = token {
+            single_bytes[*byte as usize] = true;
+        }
+        *rank_counts.entry(rank).or_default() += 1;
+    }
+    let mut duplicate_ranks: Vec<(Rank, usize)> = rank_counts
+        .iter()
+        .filter(|(_, &count)| count > 1)
+        .map(|(&rank, &count)| (rank, count))
+        .collect();
+    duplicate_ranks.sort_unstable();
+    issues.extend(
+        duplicate_ranks
+            .into_iter()
+            .map(|(rank, count)| ValidationIssue::DuplicateRank { rank, count }),
+    );
+
+    let mut sorted_special_tokens: Vec<(&String, &Rank)> = special_tokens.iter().collect();
+    sorted_special_tokens.sort();
+    let mut seen: HashMap<Rank, &String> = HashMap::default();
+    for (token, &rank) in sorted_special_tokens {
+        if rank_counts.contains_key(&rank) {
+            issues.push(ValidationIssue::SpecialTokenRankCollision {
+                token: token.clone(),
+                rank,
+            });
+        }
+        if let Some(first) = seen.insert(rank, token) {
+            issues.push(ValidationIssue::DuplicateSpecialTokenId {
+                first: first.clone(),
+                second: token.clone(),
+                rank,
+            });
+        }
+    }
+
+    let missing_bytes: Vec<u8> = (0..=u8::MAX)
+        .filter(|&byte| !single_bytes[byte as usize])
+        .collect();
+    if !missing_bytes.is_empty() {
+        issues.push(ValidationIssue::MissingSingleByteTokens {
+            bytes: missing_bytes,
+        });
+    }
+
+    let mut ids: Vec<Rank> = rank_counts.keys().chain(seen.keys()).copied().collect();
+    ids.sort_unstable();
+    ids.dedup();
+    let mut next = 0;
+    for &id in &ids {
+        if id > next {
+            issues.push(ValidationIssue::RankGap {
+                start: next,
+                end: id,
+            });
+        }
+        next = id.saturating_add(1);
+    }
+
+    let n_tokens = n_ordinary + special_tokens.len();
+    let max_token_value = ids.last().copied().unwrap_or_default();
+    if let Some(expected) = explicit_n_vocab {
+        if n_tokens != expected {
+            issues.push(ValidationIssue::UnexpectedTokenCount {
+                expected,
+                actual: n_tokens,
+            });
+        }
+        if max_token_value as usize + 1 != expected {
+            issues.push(ValidationIssue::UnexpectedMaxTokenValue {
+                expected: expected.saturating_sub(1) as Rank,
+                actual: max_token_value,
+            });
+        }
+    }
+
+    ValidationReport {
+        n_tokens,
+        max_token_value,
+        n_vocab: max_token_value as usize + 1,
+        issues,
+    }
+}
 
 struct CoreBPEConstructor {
-    encoder: HashMap<Vec<u8>, usize>,
-    special_tokens: HashMap<String, usize>,
+    vocab: Arc<Vocab>,
+    special_tokens: HashMap<String, Rank>,
     pat_str: String,
 }
 
 impl CoreBPEConstructor {
     fn new(
         tiktoken_bfe: &str,
-        special_tokens: Option<HashMap<String, usize>>,
+        special_tokens: Option<HashMap<String, Rank>>,
         pat_str: &str,
-    ) -> Self {
-        CoreBPEConstructor {
-            encoder: CoreBPEConstructor::parse_bfe(tiktoken_bfe).unwrap(),
+    ) -> Result<Self, ConstructionError> {
+        Ok(CoreBPEConstructor {
+            vocab: cached_vocab(
+                VocabKey::content(tiktoken_bfe),
+                tiktoken_bfe.as_bytes(),
+                || CoreBPEConstructor::parse_bfe(tiktoken_bfe),
+            )?,
             special_tokens: special_tokens.unwrap_or_default(),
             pat_str: String::from(pat_str),
+        })
+    }
+
+    /// Loads one of the vocabularies that `build.rs` parsed and laid out ahead of time.
+    #[cfg(feature = "embedded")]
+    fn named(
+        ranks: &str,
+        special_tokens: HashMap<String, Rank>,
+        pat_str: &str,
+    ) -> Result<Self, ConstructionError> {
+        let (key, tables) = embedded::vocab(ranks);
+        Ok(CoreBPEConstructor {
+            vocab: cached_vocab(VocabKey::Named(key), &[], || {
+                CoreBPEConstructor::load_embedded(tables)
+            })?,
+            special_tokens,
+            pat_str: String::from(pat_str),
+        })
+    }
+
+    #[cfg(all(feature = "embedded", not(feature = "compress")))]
+    fn load_embedded(tables: &'static [u8]) -> Result<Vocab, ConstructionError> {
+        Ok(Vocab::from_bytes(std::borrow::Cow::Borrowed(tables))
+            .expect("embedded vocabulary tables are corrupt"))
+    }
+
+    /// Inflates and lays out compressed ranks. This only happens the first time an encoding is
+    /// requested, the result is kept in [`VOCAB_CACHE`].
+    #[cfg(all(feature = "embedded", feature = "compress"))]
+    fn load_embedded(compressed: &'static [u8]) -> Result<Vocab, ConstructionError> {
+        let front_coded = miniz_oxide::inflate::decompress_to_vec(compressed)
+            .expect("embedded vocabulary is corrupt");
+        build_vocab(
+            VocabBuilder::from_front_coded(&front_coded).expect("embedded vocabulary is corrupt"),
+        )
+    }
+
+    /// Strictly converts a deserialized special token map, rejecting anything that is not a
+    /// non-empty string mapped to an integer id that fits in a `u32`. `null` means no tokens.
+    #[cfg_attr(not(any(feature = "wasm", feature = "node")), allow(dead_code))]
+    fn parse_special_tokens(
+        value: serde_json::Value,
+    ) -> Result<HashMap<String, Rank>, ConstructionError> {
+        let entries = match value {
+            serde_json::Value::Null => return Ok(HashMap::default()),
+            serde_json::Value::Object(entries) => entries,
+            _ => return Err(ConstructionError::SpecialTokensNotAnObject),
+        };
+
+        let mut special_tokens = HashMap::default();
+        let mut invalid = vec![];
+        for (token, id) in entries {
+            match id.as_u64() {
+                Some(id) if !token.is_empty() && id < u64::from(u32::MAX) => {
+                    special_tokens.insert(token, id as Rank);
+                }
+                _ => invalid.push(token),
+            }
+        }
+
+        if !invalid.is_empty() {
+            invalid.sort();
+            return Err(ConstructionError::InvalidSpecialTokens { tokens: invalid });
         }
+        Ok(special_tokens)
     }
 
-    fn parse_bfe(tiktoken_bfe: &str) -> Result<HashMap<Vec<u8>, usize>, Error> {
-        let mut encoder = HashMap::default();
-        for line in tiktoken_bfe.lines() {
-            let mut parts = line.split(' ');
-            let token = &general_purpose::STANDARD.decode(parts.next().unwrap())?;
-            let rank: usize = parts.next().unwrap().parse().unwrap();
-            encoder.insert(token.clone(), rank);
+    /// Checks ranks in the `.tiktoken` text format for inconsistencies, optionally against an
+    /// expected vocabulary size like the `explicit_n_vocab` argument of the Python `Encoding`.
+    /// Unlike [`CoreBPEConstructor::parse_bfe`], duplicate ranks are reported rather than
+    /// rejected, and nothing is added to [`VOCAB_CACHE`].
+    #[cfg_attr(not(any(feature = "wasm", feature = "node")), allow(dead_code))]
+    fn validate_bfe(
+        tiktoken_bfe: &str,
+        special_tokens: &HashMap<String, Rank>,
+        explicit_n_vocab: Option<usize>,
+    ) -> Result<ValidationReport, ConstructionError> {
+        let mut tokens = vec![];
+        for (index, line) in tiktoken_bfe.lines().enumerate() {
+            if !line.is_empty() {
+                let mut token = vec![];
+                let rank = CoreBPEConstructor::parse_bfe_line(index + 1, line, &mut token)?;
+                tokens.push((token, rank));
+            }
         }
 
-        Ok(encoder)
+        Ok(validate_vocabulary(
+            tokens.iter().map(|(token, rank)| (&token[..], *rank)),
+            special_tokens,
+            explicit_n_vocab,
+        ))
     }
 
-    #[cfg(feature = "inline")]
-    fn gpt2() -> Self {
+    fn parse_bfe(tiktoken_bfe: &str) -> Result<Vocab, ConstructionError> {
+        // Base64 takes four characters for every three bytes, plus the rank on each line
+        let mut builder = VocabBuilder::with_capacity(0, tiktoken_bfe.len() / 2);
+        let mut seen_ranks = HashSet::new();
+        for (index, line) in tiktoken_bfe.lines().enumerate() {
+            let line_number = index + 1;
+            if line.is_empty() {
+                continue;
+            }
+
+            let start = builder.buffer().len();
+            let rank = CoreBPEConstructor::parse_bfe_line(line_number, line, builder.buffer())?;
+            if !seen_ranks.insert(rank) {
+                return Err(ConstructionError::DuplicateRank {
+                    line: line_number,
+                    rank,
+                });
+            }
+            builder.push_written(start, rank);
+        }
+
+        build_vocab(builder)
+    }
+
+    /// Decodes the token of a non-empty line onto the end of `buffer` and returns its rank.
+    fn parse_bfe_line(
+        line_number: usize,
+        line: &str,
+        buffer: &mut Vec<u8>,
+    ) -> Result<Rank, ConstructionError> {
+        let mut parts = line.split(' ');
+        let (token, rank) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(token), Some(rank), None) if !token.is_empty() => (token, rank),
+            _ => return Err(ConstructionError::MalformedLine { line: line_number }),
+        };
+
+        general_purpose::STANDARD
+            .decode_vec(token, buffer)
+            .map_err(|e| ConstructionError::InvalidBase64 {
+                line: line_number,
+                message: e.to_string(),
+            })?;
+        // `u32::MAX` marks empty slots in the lookup tables
+        rank.parse()
+            .ok()
+            .filter(|&rank| rank < Rank::MAX)
+            .ok_or_else(|| ConstructionError::InvalidRank {
+                line: line_number,
+                rank: String::from(rank),
+            })
+    }
+
+    /// The inverse of [`CoreBPEConstructor::parse_bfe`].
+    fn export_bfe(vocab: &Vocab) -> String {
+        let mut tiktoken_bfe = String::with_capacity(vocab.len() * 16);
+        for (token, rank) in vocab.iter() {
+            general_purpose::STANDARD.encode_string(token, &mut tiktoken_bfe);
+            tiktoken_bfe.push(' ');
+            tiktoken_bfe.push_str(&rank.to_string());
+            tiktoken_bfe.push('\n');
+        }
+        tiktoken_bfe
+    }
+
+    #[cfg(feature = "gpt2")]
+    fn gpt2() -> Result<Self, ConstructionError> {
         let mut special_tokens = HashMap::default();
         special_tokens.insert(String::from(ENDOFTEXT), 50256);
 
-        CoreBPEConstructor::new(
-            include_str!("../ranks/gpt2.tiktoken"),
-            Some(special_tokens),
+        CoreBPEConstructor::named(
+            "gpt2",
+            special_tokens,
             "'s|'t|'re|'ve|'m|'ll|'d| ?\\p{L}+| ?\\p{N}+| ?[^\\s\\p{L}\\p{N}]+|\\s+(?!\\S)|\\s+",
         )
     }
 
-    #[cfg(feature = "inline")]
-    fn r50k_base() -> Self {
+    #[cfg(feature = "r50k_base")]
+    fn r50k_base() -> Result<Self, ConstructionError> {
         let mut special_tokens = HashMap::default();
         special_tokens.insert(String::from(ENDOFTEXT), 50256);
 
-        CoreBPEConstructor::new(
-            include_str!("../ranks/r50k_base.tiktoken"),
-            Some(special_tokens),
+        CoreBPEConstructor::named(
+            "r50k_base",
+            special_tokens,
             "'s|'t|'re|'ve|'m|'ll|'d| ?\\p{L}+| ?\\p{N}+| ?[^\\s\\p{L}\\p{N}]+|\\s+(?!\\S)|\\s+",
         )
     }
 
-    #[cfg(feature = "inline")]
-    fn p50k_base() -> Self {
+    #[cfg(feature = "p50k_base")]
+    fn p50k_base() -> Result<Self, ConstructionError> {
         let mut special_tokens = HashMap::default();
         special_tokens.insert(String::from(ENDOFTEXT), 50256);
 
-        CoreBPEConstructor::new(
-            include_str!("../ranks/p50k_base.tiktoken"),
-            Some(special_tokens),
+        CoreBPEConstructor::named(
+            "p50k_base",
+            special_tokens,
             "'s|'t|'re|'ve|'m|'ll|'d| ?\\p{L}+| ?\\p{N}+| ?[^\\s\\p{L}\\p{N}]+|\\s+(?!\\S)|\\s+",
         )
     }
 
-    #[cfg(feature = "inline")]
-    fn p50k_edit() -> Self {
+    #[cfg(feature = "p50k_base")]
+    fn p50k_edit() -> Result<Self, ConstructionError> {
         let mut special_tokens = HashMap::default();
         special_tokens.insert(String::from(ENDOFTEXT), 50256);
         special_tokens.insert(String::from(FIM_PREFIX), 50281);
         special_tokens.insert(String::from(FIM_MIDDLE), 50282);
         special_tokens.insert(String::from(FIM_SUFFIX), 50283);
 
-        CoreBPEConstructor::new(
-            include_str!("../ranks/p50k_base.tiktoken"),
-            Some(special_tokens),
+        CoreBPEConstructor::named(
+            "p50k_base",
+            special_tokens,
             "'s|'t|'re|'ve|'m|'ll|'d| ?\\p{L}+| ?\\p{N}+| ?[^\\s\\p{L}\\p{N}]+|\\s+(?!\\S)|\\s+",
         )
     }
 
-    #[cfg(feature = "inline")]
-    fn cl100k_base() -> Self {
+    #[cfg(feature = "cl100k_base")]
+    fn cl100k_base() -> Result<Self, ConstructionError> {
         let mut special_tokens = HashMap::default();
         special_tokens.insert(String::from(ENDOFTEXT), 100257);
         special_tokens.insert(String::from(FIM_PREFIX), 100258);
@@ -113,319 +870,86 @@ impl CoreBPEConstructor {
         special_tokens.insert(String::from(FIM_SUFFIX), 100260);
         special_tokens.insert(String::from(ENDOFPROMPT), 100276);
 
-        CoreBPEConstructor::new(
-            include_str!("../ranks/cl100k_base.tiktoken"),
-            Some(special_tokens),
+        CoreBPEConstructor::named(
+            "cl100k_base",
+            special_tokens,
             "(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\\r\\n\\p{L}\\p{N}]?\\p{L}+|\\p{N}{1,3}| ?[^\\s\\p{L}\\p{N}]+[\\r\\n]*|\\s*[\\r\\n]+|\\s+(?!\\S)|\\s+",
         )
     }
 }
 
-#[wasm_bindgen]
-pub struct Tiktoken {
-    name: Option<String>,
-    special_tokens_set: HashSet<String>,
-    bpe: CoreBPE,
-}
-
-#[wasm_bindgen]
-impl Tiktoken {
-    #[wasm_bindgen(constructor)]
-    pub fn new(tiktoken_bfe: &str, special_tokens: JsValue, pat_str: &str) -> Self {
-        let constructor = CoreBPEConstructor::new(
-            tiktoken_bfe,
-            special_tokens.into_serde::<HashMap<String, usize>>().ok(),
-            pat_str,
-        );
-
-        Tiktoken {
-            name: None,
-            special_tokens_set: constructor
-                .special_tokens
-                .keys()
-                .map(|s| s.clone())
-                .collect(),
-            bpe: CoreBPE::new(
-                constructor.encoder,
-                constructor.special_tokens,
-                &constructor.pat_str,
-            )
-            .unwrap(),
-        }
-    }
-
-    #[cfg(feature = "inline")]
-    fn with_encoding(
-        encoding: &str,
-        extend_special_tokens: &Option<HashMap<String, usize>>,
-    ) -> Result<Self, JsError> {
-        let mut constructor: CoreBPEConstructor = match encoding {
-            "gpt2" => Ok(CoreBPEConstructor::gpt2()),
-            "r50k_base" => Ok(CoreBPEConstructor::r50k_base()),
-            "p50k_base" => Ok(CoreBPEConstructor::p50k_base()),
-            "p50k_edit" => Ok(CoreBPEConstructor::p50k_edit()),
-            "cl100k_base" => Ok(CoreBPEConstructor::cl100k_base()),
-            &_ => Err(JsError::new("Invalid encoding")),
-        }?;
-
-        if let Some(tokens) = extend_special_tokens {
-            constructor.special_tokens.extend(tokens.clone());
+/// Merges `piece` into parts, each with its rank. Only single bytes that are not tokens lack a
+/// rank.
+fn _byte_pair_merge<R: Ranks + ?Sized>(
+    piece: &[u8],
+    ranks: &R,
+) -> Vec<(std::ops::Range<usize>, Option<Rank>)> {
+    // Parts are linked by the offsets they start at: `ends[start]` is where a part ends and its
+    // successor starts, and `starts[end]` is where its predecessor starts. The heap holds a
+    // candidate for each adjacent pair, lowest rank first and leftmost among equal ranks, like
+    // the naive scan. Merging invalidates the candidates of the neighbouring pairs, which are
+    // skipped when popped: a candidate is current as long as its pair still spans the same bytes.
+    //
+    // This does O(m log n) work for n parts and m merges. Pairs of tokens are looked up by
+    // their ranks with `Ranks::merged_rank`, only pairs involving a byte that is not a token
+    // hash the bytes.
+    const REMOVED: usize = usize::MAX;
+
+    let n = piece.len();
+    let mut ends: Vec<usize> = (1..=n).collect();
+    let mut starts: Vec<usize> = (0..=n).map(|i| i.saturating_sub(1)).collect();
+    let mut part_ranks: Vec<Option<Rank>> = (0..n).map(|i| ranks.rank(&piece[i..i + 1])).collect();
+    let mut heap = BinaryHeap::with_capacity(n);
+    let push_pair = |heap: &mut BinaryHeap<_>, part_ranks: &[Option<Rank>], start, mid, end| {
+        let rank = match (part_ranks[start], part_ranks[mid]) {
+            (Some(left), Some(right)) => ranks.merged_rank(left, right, &piece[start..end]),
+            _ => ranks.rank(&piece[start..end]),
+        };
+        if let Some(rank) = rank {
+            heap.push(Reverse((rank, start, end)));
         }
+    };
 
-        Ok(Tiktoken {
-            name: Some(String::from(encoding)),
-            // TODO: can we avoid cloning here?
-            special_tokens_set: constructor
-                .special_tokens
-                .keys()
-                .map(|s| s.clone())
-                .collect(),
-            bpe: CoreBPE::new(
-                constructor.encoder,
-                constructor.special_tokens,
-                &constructor.pat_str,
-            )
-            .unwrap(),
-        })
-    }
-
-    #[wasm_bindgen(getter)]
-    pub fn name(&self) -> Option<String> {
-        self.name.clone()
-    }
-
-    pub fn encode(
-        &self,
-        text: &str,
-        allowed_special: JsValue,
-        disallowed_special: JsValue,
-    ) -> Result<Vec<usize>, JsError> {
-        let allowed_tokens =
-            self.validate_allowed_tokens(text, &allowed_special, &disallowed_special)?;
-
-        Ok(self
-            .bpe
-            .encode(&text, allowed_tokens.iter().map(AsRef::as_ref).collect()))
-    }
-
-    pub fn encode_ordinary(&self, text: &str) -> Vec<usize> {
-        self.bpe.encode_ordinary(&text)
-    }
-
-    pub fn encode_with_unstable(
-        &self,
-        text: &str,
-        allowed_special: JsValue,
-        disallowed_special: JsValue,
-    ) -> Result<JsValue, JsError> {
-        let allowed_tokens =
-            self.validate_allowed_tokens(text, &allowed_special, &disallowed_special)?;
-
-        JsValue::from_serde(
-            &self
-                .bpe
-                .encode_with_unstable(&text, allowed_tokens.iter().map(AsRef::as_ref).collect()),
-        )
-        .map_err(|e| {
-            JsError::new(&format!(
-                "Failed to serialize encode_with_unstable result: {}",
-                e
-            ))
-        })
-    }
-
-    pub fn encode_single_token(&self, bytes: &[u8]) -> usize {
-        self.bpe.encode_single_token(&bytes).unwrap_throw()
-    }
-
-    #[wasm_bindgen(skip_typescript)]
-    pub fn _encode_single_piece(&self, bytes: &[u8]) -> Vec<usize> {
-        self.bpe.encode_single_piece(&bytes)
-    }
-
-    pub fn decode(&self, tokens: Vec<usize>) -> Vec<u8> {
-        self.bpe.decode_bytes(tokens)
-    }
-
-    pub fn decode_single_token_bytes(&self, token: usize) -> Vec<u8> {
-        self.bpe.decode_single_token_bytes(token).unwrap_throw()
-    }
-
-    pub fn token_byte_values(&self) -> JsValue {
-        JsValue::from_serde(&self.bpe.token_byte_values()).unwrap_throw()
+    for start in 0..n.saturating_sub(1) {
+        push_pair(&mut heap, &part_ranks, start, start + 1, start + 2);
     }
 
-    fn validate_allowed_tokens(
-        &self,
-        text: &str,
-        allowed_special_param: &JsValue,
-        disallowed_special_param: &JsValue,
-    ) -> Result<HashSet<String>, JsError> {
-        let allowed_special: HashSet<String> = match allowed_special_param.as_string() {
-            Some(value) => match value.as_str() {
-                "all" => Ok(self.special_tokens_set.clone()),
-                _ => Err(JsError::new("Invalid value for allowed_special")),
-            },
-            _ => Ok(JsValue::into_serde(&allowed_special_param).unwrap_or_default()),
-        }?;
-
-        let disallowed_special = JsValue::into_serde::<HashSet<String>>(&disallowed_special_param)
-            .or_else(|_| {
-                match disallowed_special_param
-                    .as_string()
-                    .unwrap_or(String::from("all"))
-                    .as_str()
-                {
-                    "all" => Ok(&self.special_tokens_set - &allowed_special),
-                    _ => Err(JsError::new("Invalid value for disallowed_special")),
-                }
-            })?;
-
-        if !disallowed_special.is_empty() {
-            if let Some(found) = Tiktoken::special_token_regex(&disallowed_special).find(text)? {
-                return Err(JsError::new(&format!(
-                    "The text contains a special token that is not allowed: {}",
-                    found.as_str()
-                )));
-            }
+    while let Some(Reverse((rank, start, end))) = heap.pop() {
+        let mid = ends[start];
+        if mid == REMOVED || mid == n || ends[mid] != end {
+            continue;
+        }
+        ends[start] = end;
+        ends[mid] = REMOVED;
+        starts[end] = start;
+        part_ranks[start] = Some(rank);
+        if end < n {
+            push_pair(&mut heap, &part_ranks, start, end, ends[end]);
+        }
+        if start > 0 {
+            push_pair(&mut heap, &part_ranks, starts[start], start, end);
         }
-
-        return Ok(allowed_special);
     }
 
-    fn special_token_regex(tokens: &HashSet<String>) -> Regex {
-        let inner = tokens
-            .iter()
-            .map(|token| regex::escape(token))
-            .collect::<Vec<String>>()
-            .join("|");
-
-        Regex::new(&format!("({})", inner)).unwrap_throw()
+    let mut parts = Vec::new();
+    let mut start = 0;
+    while start < n {
+        parts.push((start..ends[start], part_ranks[start]));
+        start = ends[start];
     }
+    parts
 }
 
-#[cfg(feature = "inline")]
-#[wasm_bindgen(typescript_custom_section)]
-const _: &'static str = r#"
-export type TiktokenEmbedding = "gpt2" | "r50k_base" | "p50k_base" | "p50k_edit" | "cl100k_base"; 
-
-/**
- * @param {TiktokenEmbedding} encoding
- * @param {Record<string, number>} [extend_special_tokens]
- * @returns {Tiktoken}
- */
-export function get_encoding(encoding: TiktokenEmbedding, extend_special_tokens?: Record<string, number>): Tiktoken;
-"#;
-
-#[cfg(feature = "inline")]
-#[wasm_bindgen(skip_typescript)]
-pub fn get_encoding(encoding: &str, extend_special_tokens: JsValue) -> Result<Tiktoken, JsError> {
-    Tiktoken::with_encoding(
-        encoding,
-        &extend_special_tokens
-            .into_serde::<HashMap<String, usize>>()
-            .ok(),
-    )
-}
-
-#[cfg(feature = "inline")]
-#[wasm_bindgen(typescript_custom_section)]
-const _: &'static str = r#"
-export type TiktokenModel =
-    | "text-davinci-003"
-    | "text-davinci-002"
-    | "text-davinci-001"
-    | "text-curie-001"
-    | "text-babbage-001"
-    | "text-ada-001"
-    | "davinci"
-    | "curie"
-    | "babbage"
-    | "ada"
-    | "code-davinci-002"
-    | "code-davinci-001"
-    | "code-cushman-002"
-    | "code-cushman-001"
-    | "davinci-codex"
-    | "cushman-codex"
-    | "text-davinci-edit-001"
-    | "code-davinci-edit-001"
-    | "text-embedding-ada-002"
-    | "text-similarity-davinci-001"
-    | "text-similarity-curie-001"
-    | "text-similarity-babbage-001"
-    | "text-similarity-ada-001"
-    | "text-search-davinci-doc-001"
-    | "text-search-curie-doc-001"
-    | "text-search-babbage-doc-001"
-    | "text-search-ada-doc-001"
-    | "code-search-babbage-code-001"
-    | "code-search-ada-code-001"
-    | "gpt2";
-
-/**
- * @param {TiktokenModel} encoding
- * @param {Record<string, number>} [extend_special_tokens]
- * @returns {Tiktoken}
- */
-export function encoding_for_model(model: TiktokenModel, extend_special_tokens?: Record<string, number>): Tiktoken;
-"#;
-
-#[cfg(feature = "inline")]
-#[wasm_bindgen(skip_typescript)]
-pub fn encoding_for_model(
-    model: &str,
-    extend_special_tokens: JsValue,
-) -> Result<Tiktoken, JsError> {
-    let encoding = match model {
-        "text-davinci-003" => Ok("p50k_base"),
-        "text-davinci-002" => Ok("p50k_base"),
-        "text-davinci-001" => Ok("r50k_base"),
-        "text-curie-001" => Ok("r50k_base"),
-        "text-babbage-001" => Ok("r50k_base"),
-        "text-ada-001" => Ok("r50k_base"),
-        "davinci" => Ok("r50k_base"),
-        "curie" => Ok("r50k_base"),
-        "babbage" => Ok("r50k_base"),
-        "ada" => Ok("r50k_base"),
-        "code-davinci-002" => Ok("p50k_base"),
-        "code-davinci-001" => Ok("p50k_base"),
-        "code-cushman-002" => Ok("p50k_base"),
-        "code-cushman-001" => Ok("p50k_base"),
-        "davinci-codex" => Ok("p50k_base"),
-        "cushman-codex" => Ok("p50k_base"),
-        "text-davinci-edit-001" => Ok("p50k_edit"),
-        "code-davinci-edit-001" => Ok("p50k_edit"),
-        "text-embedding-ada-002" => Ok("cl100k_base"),
-        "text-similarity-davinci-001" => Ok("r50k_base"),
-        "text-similarity-curie-001" => Ok("r50k_base"),
-        "text-similarity-babbage-001" => Ok("r50k_base"),
-        "text-similarity-ada-001" => Ok("r50k_base"),
-        "text-search-davinci-doc-001" => Ok("r50k_base"),
-        "text-search-curie-doc-001" => Ok("r50k_base"),
-        "text-search-babbage-doc-001" => Ok("r50k_base"),
-        "text-search-ada-doc-001" => Ok("r50k_base"),
-        "code-search-babbage-code-001" => Ok("r50k_base"),
-        "code-search-ada-code-001" => Ok("r50k_base"),
-        "gpt2" => Ok("gpt2"),
-        model => Err(JsError::new(
-            format!("Invalid model: {}", model.to_string()).as_str(),
-        )),
-    }?;
-
-    Tiktoken::with_encoding(
-        encoding,
-        &extend_special_tokens
-            .into_serde::<HashMap<String, usize>>()
-            .ok(),
-    )
-}
-
-fn _byte_pair_merge(piece: &[u8], ranks: &HashMap<Vec<u8>, usize>) -> Vec<std::ops::Range<usize>> {
+/// The original merge, which rescans every pair after each merge. `_byte_pair_merge` must
+/// produce exactly the same parts.
+#[cfg(test)]
+fn _byte_pair_merge_naive<R: Ranks + ?Sized>(
+    piece: &[u8],
+    ranks: &R,
+) -> Vec<std::ops::Range<usize>> {
     let mut parts: Vec<_> = (0..piece.len()).map(|i| i..i + 1).collect();
 
     // If you have n parts and m merges, this does O(mn) work
-    // We could do something with a heap and do O(m log n) work
 
     // Note that we hash bytes, not token pairs. As long as we train BPE the way we
     // currently do, this is equivalent. An easy way to break this would be to decouple
@@ -434,10 +958,10 @@ fn _byte_pair_merge(piece: &[u8], ranks: &HashMap<Vec<u8>, usize>) -> Vec<std::o
         if parts.len() == 1 {
             break;
         }
-        let mut min_rank: Option<(usize, usize)> = None;
+        let mut min_rank: Option<(Rank, usize)> = None;
         for i in 0..parts.len() - 1 {
-            let rank = if let Some(r) = ranks.get(&piece[parts[i].start..parts[i + 1].end]) {
-                *r
+            let rank = if let Some(r) = ranks.rank(&piece[parts[i].start..parts[i + 1].end]) {
+                r
             } else {
                 continue;
             };
@@ -455,23 +979,24 @@ fn _byte_pair_merge(piece: &[u8], ranks: &HashMap<Vec<u8>, usize>) -> Vec<std::o
     parts
 }
 
-pub fn byte_pair_encode(piece: &[u8], ranks: &HashMap<Vec<u8>, usize>) -> Vec<usize> {
+pub fn byte_pair_encode<R: Ranks + ?Sized>(piece: &[u8], ranks: &R) -> Vec<Rank> {
+    let known = |rank: Option<Rank>| rank.expect("piece is not in the vocabulary");
     if piece.len() == 1 {
-        return vec![ranks[piece]];
+        return vec![known(ranks.rank(piece))];
     }
     _byte_pair_merge(piece, ranks)
-        .iter()
-        .map(|p| ranks[&piece[p.start..p.end]])
+        .into_iter()
+        .map(|(_, rank)| known(rank))
         .collect()
 }
 
-pub fn byte_pair_split<'a>(piece: &'a [u8], ranks: &HashMap<Vec<u8>, usize>) -> Vec<&'a [u8]> {
+pub fn byte_pair_split<'a, R: Ranks + ?Sized>(piece: &'a [u8], ranks: &R) -> Vec<&'a [u8]> {
     if piece.len() == 1 {
         return vec![piece];
     }
     _byte_pair_merge(piece, ranks)
-        .iter()
-        .map(|p| &piece[p.start..p.end])
+        .into_iter()
+        .map(|(part, _)| &piece[part])
         .collect()
 }
 
@@ -513,20 +1038,41 @@ pub fn byte_pair_split<'a>(piece: &'a [u8], ranks: &HashMap<Vec<u8>, usize>) ->
 // Hashing
 // =======
 // We use FxHashMap instead of the standard HashMap. This is maybe like a 5-10% win?
-// The current implementation ends up doing a lot of hashing of bytes. In theory, this could be made
-// to be hashing of two-tuples of ints, which looks like it may also be a couple percent faster.
-
-use std::num::NonZeroU64;
-pub struct FakeThreadId(NonZeroU64);
+// The merge used to hash the bytes of every candidate pair. Now that parts carry their ranks, it
+// looks pairs up as two-tuples of ints in the vocabulary's merge table instead, and only hashes
+// bytes that are not tokens themselves.
+
+/// Lays out the collected tokens, reporting ranks that are used more than once or that are out
+/// of range. Every way of building a vocabulary goes through here.
+fn build_vocab(builder: VocabBuilder) -> Result<Vocab, ConstructionError> {
+    builder.build().map_err(|error| match error {
+        BuildError::DuplicateRanks(duplicates) => {
+            let issues = duplicates
+                .into_iter()
+                .map(|(rank, count)| ValidationIssue::DuplicateRank { rank, count })
+                .collect();
+            ConstructionError::InvalidVocabulary { issues }
+        }
+        BuildError::RankOutOfRange { rank, n_tokens } => {
+            ConstructionError::RankOutOfRange { rank, n_tokens }
+        }
+    })
+}
 
-struct CoreBPE {
-    encoder: HashMap<Vec<u8>, usize>,
-    special_tokens_encoder: HashMap<String, usize>,
-    decoder: HashMap<usize, Vec<u8>>,
-    special_tokens_decoder: HashMap<usize, Vec<u8>>,
+/// The tokeniser behind an [`Encoding`]: a vocabulary of ordinary tokens, the special tokens
+/// and the split pattern. Cloning is cheap, the vocabulary is shared.
+///
+/// Unlike [`Encoding`], the `encode` methods here take the set of allowed special tokens as
+/// is and never reject text.
+#[derive(Clone)]
+pub struct CoreBPE {
+    vocab: Arc<Vocab>,
+    special_tokens_encoder: HashMap<String, Rank>,
+    special_tokens_decoder: HashMap<Rank, Vec<u8>>,
     regex: Regex,
     special_regex: Regex,
-    sorted_token_bytes: Vec<Vec<u8>>,
+    pattern: String,
+    max_token_value: Rank,
 }
 
 impl CoreBPE {
@@ -541,35 +1087,38 @@ impl CoreBPE {
         &self.special_regex
     }
 
-    fn _decode_native(&self, tokens: &[usize]) -> Vec<u8> {
+    fn _decode_native(&self, tokens: &[Rank]) -> Result<Vec<u8>, Error> {
         let mut ret = Vec::with_capacity(tokens.len() * 2);
-        for token in tokens {
-            let token_bytes = self
-                .decoder
-                .get(token)
-                .unwrap_or_else(|| &self.special_tokens_decoder[token]);
+        for &token in tokens {
+            let token_bytes = match self.vocab.get(token) {
+                Some(bytes) => bytes,
+                None => self
+                    .special_tokens_decoder
+                    .get(&token)
+                    .ok_or(Error::UnknownToken { token })?,
+            };
             ret.extend(token_bytes);
         }
-        ret
+        Ok(ret)
     }
 
-    fn _encode_ordinary_native(&self, text: &str) -> Vec<usize> {
+    fn _encode_ordinary_native(&self, text: &str) -> Vec<Rank> {
         // This is the core of the encoding logic; the other functions in here
         // just make things complicated :-)
         let regex = self._get_tl_regex();
         let mut ret = vec![];
         for mat in regex.find_iter(text) {
             let piece = mat.unwrap().as_str().as_bytes();
-            if let Some(token) = self.encoder.get(piece) {
-                ret.push(*token);
+            if let Some(token) = self.vocab.rank(piece) {
+                ret.push(token);
                 continue;
             }
-            ret.extend(&byte_pair_encode(piece, &self.encoder));
+            ret.extend(&byte_pair_encode(piece, &*self.vocab));
         }
         ret
     }
 
-    fn _encode_native(&self, text: &str, allowed_special: &HashSet<&str>) -> (Vec<usize>, usize) {
+    fn _encode_native(&self, text: &str, allowed_special: &HashSet<&str>) -> (Vec<Rank>, usize) {
         let special_regex = self._get_tl_special_regex();
         let regex = self._get_tl_regex();
         let mut ret = vec![];
@@ -597,12 +1146,12 @@ impl CoreBPE {
             // Okay, here we go, compare this logic to _encode_ordinary_native
             for mat in regex.find_iter(&text[start..end]) {
                 let piece = mat.unwrap().as_str().as_bytes();
-                if let Some(token) = self.encoder.get(piece) {
+                if let Some(token) = self.vocab.rank(piece) {
                     last_piece_token_len = 1;
-                    ret.push(*token);
+                    ret.push(token);
                     continue;
                 }
-                let tokens = byte_pair_encode(piece, &self.encoder);
+                let tokens = byte_pair_encode(piece, &*self.vocab);
                 last_piece_token_len = tokens.len();
                 ret.extend(&tokens);
             }
@@ -627,9 +1176,9 @@ impl CoreBPE {
 
     fn _increase_last_piece_token_len(
         &self,
-        tokens: Vec<usize>,
+        tokens: Vec<Rank>,
         mut last_piece_token_len: usize,
-    ) -> (Vec<usize>, usize) {
+    ) -> (Vec<Rank>, usize) {
         // Unfortunately, the locations where our regex splits can be unstable.
         // For the purposes of determining unstable tokens, unstable regex splitting
         // is only a problem if a split that was present disappears, since this can
@@ -638,9 +1187,9 @@ impl CoreBPE {
         // pattern. This can e.g. cause "\n" + " " to become "\n \n".
         // Here is a quick and dirty fix:
         {
-            let token_is_all_space = |token| {
-                self.decoder
-                    .get(token)
+            let token_is_all_space = |token: &Rank| {
+                self.vocab
+                    .get(*token)
                     .map(|token_bytes| {
                         token_bytes
                             .iter()
@@ -668,7 +1217,7 @@ impl CoreBPE {
         &self,
         text: &str,
         allowed_special: &HashSet<&str>,
-    ) -> (Vec<usize>, HashSet<Vec<usize>>) {
+    ) -> (Vec<Rank>, HashSet<Vec<Rank>>) {
         let (tokens, last_piece_token_len) = self._encode_native(text, allowed_special);
         if last_piece_token_len == 0 {
             // If last_piece_token_len is zero, the last token was a special token and we have
@@ -678,7 +1227,10 @@ impl CoreBPE {
         let (mut tokens, last_piece_token_len) =
             self._increase_last_piece_token_len(tokens, last_piece_token_len);
 
-        let unstable_bytes = self._decode_native(&tokens[tokens.len() - last_piece_token_len..]);
+        // Every token came from this encoding, so decoding cannot fail
+        let unstable_bytes = self
+            ._decode_native(&tokens[tokens.len() - last_piece_token_len..])
+            .unwrap();
         tokens.truncate(tokens.len() - last_piece_token_len);
 
         // TODO: we should try harder to find additional stable tokens
@@ -693,15 +1245,15 @@ impl CoreBPE {
         // This is the easy bit. Just find all single tokens that start with unstable_bytes
         // (including tokens that exactly match unstable_bytes)
         // Separating this from the loop below helps with performance in a common case.
-        let mut point = self
-            .sorted_token_bytes
-            .partition_point(|x| x.as_slice() < unstable_bytes.as_slice());
-        while point < self.sorted_token_bytes.len()
-            && self.sorted_token_bytes[point].starts_with(&unstable_bytes)
+        let mut point = self.vocab.sorted_partition_point(&unstable_bytes);
+        while point < self.vocab.len()
+            && self
+                .vocab
+                .sorted_token(point)
+                .0
+                .starts_with(&unstable_bytes)
         {
-            completions.insert(vec![
-                self.encoder[self.sorted_token_bytes[point].as_slice()],
-            ]);
+            completions.insert(vec![self.vocab.sorted_token(point).1]);
             point += 1;
         }
 
@@ -711,16 +1263,12 @@ impl CoreBPE {
         for i in 1..unstable_bytes.len() {
             let prefix = &unstable_bytes[..i];
             let suffix = &unstable_bytes[i..];
-            let mut point = self
-                .sorted_token_bytes
-                .partition_point(|x| x.as_slice() < suffix);
+            let mut point = self.vocab.sorted_partition_point(suffix);
             // TODO: Perf optimisation if suffix starts with " "?
-            while point < self.sorted_token_bytes.len()
-                && self.sorted_token_bytes[point].starts_with(suffix)
-            {
-                let possibility = [prefix, self.sorted_token_bytes[point].as_slice()].concat();
+            while point < self.vocab.len() && self.vocab.sorted_token(point).0.starts_with(suffix) {
+                let possibility = [prefix, self.vocab.sorted_token(point).0].concat();
                 let encoded = match std::str::from_utf8(&possibility) {
-                    // Morally, this is byte_pair_encode(&possibility, &self.encoder)
+                    // Morally, this is byte_pair_encode(&possibility, &*self.vocab)
                     // But we might have introduced a regex split which would prevent merges.
                     // (particularly possible in the presence of unstable regex splits)
                     // So convert to UTF-8 and do regex splitting.
@@ -732,7 +1280,7 @@ impl CoreBPE {
                     // would be a regex split before the UTF-8 truncation point.
                     // Probably niche enough that no one will ever notice (after all, people didn't
                     // notice all the big holes in the previous unstable token implementation)
-                    Err(_) => byte_pair_encode(&possibility, &self.encoder),
+                    Err(_) => byte_pair_encode(&possibility, &*self.vocab),
                     // Something like the following is intriguing but incorrect:
                     // Err(e) => self._encode_ordinary_native(unsafe {
                     //     std::str::from_utf8_unchecked(&possibility[..e.valid_up_to()])
@@ -742,7 +1290,7 @@ impl CoreBPE {
                 let mut seq_len = 0;
                 for token in encoded {
                     seq.push(token);
-                    seq_len += self.decoder[&token].len();
+                    seq_len += self.vocab.get(token).map_or(0, <[u8]>::len);
                     if seq_len >= unstable_bytes.len() {
                         break;
                     }
@@ -767,11 +1315,11 @@ impl CoreBPE {
             {
                 let mut reencoded = byte_pair_encode(
                     &unstable_bytes[..unstable_bytes.len() - last_decoded.1],
-                    &self.encoder,
+                    &*self.vocab,
                 );
                 reencoded.extend(byte_pair_encode(
                     &unstable_bytes[unstable_bytes.len() - last_decoded.1..],
-                    &self.encoder,
+                    &*self.vocab,
                 ));
                 completions.insert(reencoded);
             }
@@ -782,59 +1330,178 @@ impl CoreBPE {
 }
 
 impl CoreBPE {
-    fn new(
-        encoder: HashMap<Vec<u8>, usize>,
-        special_tokens_encoder: HashMap<String, usize>,
+    /// Builds a tokeniser from ranks in the `.tiktoken` text format, special tokens and a split
+    /// pattern. Unlike [`Encoding::new`], the parsed ranks are not cached.
+    pub fn new(
+        tiktoken_bfe: &str,
+        special_tokens_encoder: impl IntoIterator<Item = (String, Rank)>,
         pattern: &str,
-    ) -> Result<Self, Error> {
-        let regex = Regex::new(pattern)?;
+    ) -> Result<Self, ConstructionError> {
+        CoreBPE::from_vocab(
+            Arc::new(CoreBPEConstructor::parse_bfe(tiktoken_bfe)?),
+            special_tokens_encoder.into_iter().collect(),
+            pattern,
+        )
+    }
 
+    /// Builds a tokeniser from decoded ranks, like the `mergeable_ranks` of the Python
+    /// `Encoding`.
+    pub fn from_ranks<T: AsRef<[u8]>>(
+        ranks: impl IntoIterator<Item = (T, Rank)>,
+        special_tokens_encoder: impl IntoIterator<Item = (String, Rank)>,
+        pattern: &str,
+    ) -> Result<Self, ConstructionError> {
+        let mut builder = VocabBuilder::default();
+        for (token, rank) in ranks {
+            builder.push(token.as_ref(), rank);
+        }
+        CoreBPE::from_vocab(
+            Arc::new(build_vocab(builder)?),
+            special_tokens_encoder.into_iter().collect(),
+            pattern,
+        )
+    }
+
+    fn from_vocab(
+        vocab: Arc<Vocab>,
+        special_tokens_encoder: HashMap<String, Rank>,
+        pattern: &str,
+    ) -> Result<Self, ConstructionError> {
+        let regex = Regex::new(pattern).map_err(ConstructionError::invalid_pattern)?;
+        CoreBPE::from_parts(vocab, special_tokens_encoder, regex, pattern)
+    }
+
+    fn from_parts(
+        vocab: Arc<Vocab>,
+        special_tokens_encoder: HashMap<String, Rank>,
+        regex: Regex,
+        pattern: &str,
+    ) -> Result<Self, ConstructionError> {
         let special_regex = {
             let _parts = special_tokens_encoder
                 .keys()
                 .map(|s| fancy_regex::escape(s))
                 .collect::<Vec<_>>();
-            Regex::new(&_parts.join("|"))?
+            Regex::new(&_parts.join("|")).map_err(ConstructionError::invalid_pattern)?
         };
 
-        let decoder: HashMap<usize, Vec<u8>> =
-            encoder.iter().map(|(k, v)| (*v, k.clone())).collect();
-
-        assert!(encoder.len() == decoder.len());
-
-        let special_tokens_decoder: HashMap<usize, Vec<u8>> = special_tokens_encoder
+        // Sorted so that the reported collision does not depend on hash order
+        let mut special_tokens: Vec<(&String, &Rank)> = special_tokens_encoder.iter().collect();
+        special_tokens.sort();
+        let invalid: Vec<String> = special_tokens
             .iter()
-            .map(|(k, v)| (*v, k.as_bytes().to_vec()))
+            .filter(|(_, &rank)| rank == Rank::MAX)
+            .map(|(token, _)| String::clone(token))
             .collect();
+        if !invalid.is_empty() {
+            return Err(ConstructionError::InvalidSpecialTokens { tokens: invalid });
+        }
+        let mut special_tokens_decoder: HashMap<Rank, Vec<u8>> = HashMap::default();
+        for (token, &rank) in special_tokens {
+            if vocab.contains_rank(rank) {
+                return Err(ConstructionError::SpecialTokenRankCollision {
+                    token: token.clone(),
+                    rank,
+                });
+            }
+            if let Some(first) = special_tokens_decoder.insert(rank, token.as_bytes().to_vec()) {
+                return Err(ConstructionError::DuplicateSpecialTokenId {
+                    first: String::from_utf8(first).unwrap(),
+                    second: token.clone(),
+                    rank,
+                });
+            }
+        }
+
+        // Any byte can end up in a piece on its own, and byte_pair_encode needs a rank for it
+        let bytes: Vec<u8> = (0..=u8::MAX)
+            .filter(|&byte| vocab.rank(&[byte]).is_none())
+            .collect();
+        if !bytes.is_empty() {
+            return Err(ConstructionError::InvalidVocabulary {
+                issues: vec![ValidationIssue::MissingSingleByteTokens { bytes }],
+            });
+        }
 
-        // Clone because I don't know how to tell Rust I'm not going to change the map
-        let mut sorted_token_bytes: Vec<Vec<u8>> = encoder.keys().cloned().collect();
-        sorted_token_bytes.sort();
+        let max_token_value = vocab
+            .max_rank()
+            .into_iter()
+            .chain(special_tokens_decoder.keys().copied())
+            .max()
+            .unwrap_or_default();
 
         Ok(CoreBPE {
-            encoder,
+            vocab,
             special_tokens_encoder,
-            decoder,
             special_tokens_decoder,
             regex,
             special_regex,
-            sorted_token_bytes,
+            pattern: String::from(pattern),
+            max_token_value,
+        })
+    }
+
+    /// Derives an encoding with a different set of special tokens, sharing the vocabulary and
+    /// the compiled split pattern.
+    fn with_special_tokens(
+        &self,
+        special_tokens_encoder: HashMap<String, Rank>,
+    ) -> Result<Self, ConstructionError> {
+        CoreBPE::from_parts(
+            self.vocab.clone(),
+            special_tokens_encoder,
+            self.regex.clone(),
+            &self.pattern,
+        )
+    }
+
+    /// Derives an encoding with a different split pattern, sharing the vocabulary.
+    fn with_pattern(&self, pattern: &str) -> Result<Self, ConstructionError> {
+        let regex = Regex::new(pattern).map_err(ConstructionError::invalid_pattern)?;
+        Ok(CoreBPE {
+            vocab: self.vocab.clone(),
+            special_tokens_encoder: self.special_tokens_encoder.clone(),
+            special_tokens_decoder: self.special_tokens_decoder.clone(),
+            regex,
+            special_regex: self.special_regex.clone(),
+            pattern: String::from(pattern),
+            max_token_value: self.max_token_value,
         })
     }
 
+    /// The split pattern.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// The special tokens and their ids.
+    pub fn special_tokens(&self) -> impl ExactSizeIterator<Item = (&str, Rank)> + '_ {
+        self.special_tokens_encoder
+            .iter()
+            .map(|(token, &rank)| (token.as_str(), rank))
+    }
+
+    /// The largest ordinary or special token id.
+    pub fn max_token_value(&self) -> Rank {
+        self.max_token_value
+    }
+
     // ====================
     // Encoding
     // ====================
 
-    fn encode_ordinary(&self, text: &str) -> Vec<usize> {
+    /// Encodes `text`, treating special tokens as ordinary text.
+    pub fn encode_ordinary(&self, text: &str) -> Vec<Rank> {
         self._encode_ordinary_native(text)
     }
 
-    fn encode(&self, text: &str, allowed_special: HashSet<&str>) -> Vec<usize> {
-        self._encode_native(text, &allowed_special).0
+    /// Encodes `text`, turning the special tokens in `allowed_special` into their ids and
+    /// treating any others as ordinary text.
+    pub fn encode(&self, text: &str, allowed_special: &HashSet<&str>) -> Vec<Rank> {
+        self._encode_native(text, allowed_special).0
     }
 
-    fn _encode_bytes(&self, bytes: &[u8]) -> Vec<usize> {
+    fn _encode_bytes(&self, bytes: &[u8]) -> Vec<Rank> {
         {
             match std::str::from_utf8(bytes) {
                 Ok(text) => self._encode_ordinary_native(text),
@@ -848,12 +1515,13 @@ impl CoreBPE {
                         // Somewhat niche, but this may not be correct if we'd have had a regex
                         // split between the valid UTF-8 and the invalid bytes, which is why this
                         // method is private
-                        let mut unstable_bytes =
-                            self._decode_native(&tokens[tokens.len() - last_piece_token_len..]);
+                        let mut unstable_bytes = self
+                            ._decode_native(&tokens[tokens.len() - last_piece_token_len..])
+                            .unwrap();
                         unstable_bytes.extend_from_slice(&bytes[e.valid_up_to()..]);
 
                         tokens.truncate(tokens.len() - last_piece_token_len);
-                        tokens.extend(byte_pair_encode(&unstable_bytes, &self.encoder));
+                        tokens.extend(byte_pair_encode(&unstable_bytes, &*self.vocab));
                     }
                     tokens
                 }
@@ -861,16 +1529,20 @@ impl CoreBPE {
         }
     }
 
-    fn encode_with_unstable(
+    /// Encodes `text` like [`CoreBPE::encode`], but leaves out the tokens at the end that could
+    /// merge differently once more text is appended. Returns the stable tokens and every
+    /// sequence of tokens that could continue them.
+    pub fn encode_with_unstable(
         &self,
         text: &str,
-        allowed_special: HashSet<&str>,
-    ) -> (Vec<usize>, HashSet<Vec<usize>>) {
-        self._encode_unstable_native(text, &allowed_special)
+        allowed_special: &HashSet<&str>,
+    ) -> (Vec<Rank>, HashSet<Vec<Rank>>) {
+        self._encode_unstable_native(text, allowed_special)
     }
 
-    fn encode_single_token(&self, piece: &[u8]) -> Result<usize, Error> {
-        if let Some(token) = self.encoder.get(piece).copied() {
+    /// The id of the ordinary or special token consisting of exactly `piece`.
+    pub fn encode_single_token(&self, piece: &[u8]) -> Result<Rank, Error> {
+        if let Some(token) = self.vocab.rank(piece) {
             return Ok(token);
         }
         if let Ok(piece_str) = std::str::from_utf8(piece) {
@@ -878,51 +1550,73 @@ impl CoreBPE {
                 return Ok(token);
             }
         }
-        Err(anyhow!("Unable to encode single token: {:?}", piece))
+        Err(Error::NotASingleToken {
+            bytes: piece.to_vec(),
+        })
     }
 
-    fn encode_single_piece(&self, piece: &[u8]) -> Vec<usize> {
-        if let Some(token) = self.encoder.get(piece) {
-            return vec![*token];
+    /// Encodes `piece` without splitting it with the pattern first.
+    pub fn encode_single_piece(&self, piece: &[u8]) -> Vec<Rank> {
+        if let Some(token) = self.vocab.rank(piece) {
+            return vec![token];
         }
-        byte_pair_encode(piece, &self.encoder)
+        byte_pair_encode(piece, &*self.vocab)
     }
 
     // ====================
     // Decoding
     // ====================
 
-    fn decode_bytes(&self, tokens: Vec<usize>) -> Vec<u8> {
-        self._decode_native(&tokens)
+    /// The bytes of `tokens`, which need not be valid UTF-8 on their own.
+    pub fn decode_bytes(&self, tokens: &[Rank]) -> Result<Vec<u8>, Error> {
+        self._decode_native(tokens)
     }
 
-    fn decode_single_token_bytes(&self, token: usize) -> Result<Vec<u8>, Error> {
-        if let Some(bytes) = self.decoder.get(&token) {
-            return Ok(bytes.clone());
+    /// The bytes of a single ordinary or special token.
+    pub fn decode_single_token_bytes(&self, token: Rank) -> Result<Vec<u8>, Error> {
+        if let Some(bytes) = self.vocab.get(token) {
+            return Ok(bytes.to_vec());
         }
         if let Some(bytes) = self.special_tokens_decoder.get(&token) {
             return Ok(bytes.clone());
         }
-        Err(anyhow!(
-            "Token not found in the vocabulary: {}",
-            token.to_string()
-        ))
+        Err(Error::UnknownToken { token })
     }
 
     // ====================
     // Miscellaneous
     // ====================
 
-    fn token_byte_values(&self) -> Vec<Vec<u8>> {
-        self.sorted_token_bytes.clone()
+    /// The bytes of every ordinary token, in byte order.
+    pub fn token_byte_values(&self) -> Vec<Vec<u8>> {
+        self.vocab.sorted_tokens().map(<[u8]>::to_vec).collect()
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use base64::{engine::general_purpose, Engine as _};
     use rustc_hash::FxHashMap as HashMap;
-
-    use crate::byte_pair_split;
+    use std::borrow::Cow;
+    use std::sync::Arc;
+
+    use crate::{
+        _byte_pair_merge, _byte_pair_merge_naive, binary, build_vocab, byte_pair_split,
+        clear_encoding_cache, huggingface, snapshot, validate_vocabulary, AllowedSpecial,
+        CachedVocab, ConstructionError, CoreBPE, CoreBPEConstructor, DisallowedSpecial, Encoding,
+        Error, Rank, Ranks, ValidationIssue, Vocab, VocabBuilder, VocabKey, VOCAB_CACHE,
+    };
+
+    /// Ranks in the `.tiktoken` format that give every byte its value as rank, followed by
+    /// `tokens` from 256 on.
+    fn byte_ranks(tokens: &[&str]) -> String {
+        (0..=u8::MAX)
+            .map(|byte| vec![byte])
+            .chain(tokens.iter().map(|token| token.as_bytes().to_vec()))
+            .enumerate()
+            .map(|(rank, token)| format!("{} {}\n", general_purpose::STANDARD.encode(token), rank))
+            .collect()
+    }
 
     #[test]
     fn very_simple_test() {
@@ -933,4 +1627,597 @@ mod tests {
         let res = byte_pair_split(b"abcd", &ranks);
         assert_eq!(res, vec![b"ab", b"cd"]);
     }
+
+    #[test]
+    fn heap_merge_matches_naive_merge() {
+        // The parts must be those of the naive merge, with the ranks of their bytes
+        fn check<R: Ranks + ?Sized>(piece: &[u8], ranks: &R, naive: &HashMap<Vec<u8>, Rank>) {
+            let parts = _byte_pair_merge(piece, ranks);
+            let ranges: Vec<_> = parts.iter().map(|(part, _)| part.clone()).collect();
+            assert_eq!(
+                ranges,
+                _byte_pair_merge_naive(piece, naive),
+                "{:?}",
+                String::from_utf8_lossy(piece)
+            );
+            for (part, rank) in parts {
+                assert_eq!(rank, naive.get(&piece[part]).copied());
+            }
+        }
+
+        // A fixed xorshift sequence, so that failures reproduce
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = |bound: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound as u64) as usize
+        };
+
+        // Few symbols and many overlapping tokens, so that pieces hit ties and stale pairs. `b`
+        // is not a token, so pairs with it are looked up by their bytes.
+        let alphabet = b"ab ";
+        let mut ranks: HashMap<Vec<u8>, Rank> = HashMap::default();
+        ranks.insert(b"a".to_vec(), 0);
+        ranks.insert(b" ".to_vec(), 1);
+        for _ in 0..60 {
+            let token: Vec<u8> = (0..2 + next(5)).map(|_| alphabet[next(3)]).collect();
+            ranks.insert(token, next(40) as Rank);
+        }
+        let spaces: Vec<u8> = vec![b' '; 1000];
+        for len in [2, 4, 8, 16] {
+            ranks.insert(spaces[..len].to_vec(), 100 + len as Rank);
+        }
+
+        // The same tokens in a vocabulary, which needs distinct ranks
+        let mut tokens: Vec<_> = ranks.iter().map(|(token, &rank)| (rank, token)).collect();
+        tokens.sort();
+        let mut unique_ranks = HashMap::default();
+        let mut builder = VocabBuilder::default();
+        for (rank, (_, token)) in tokens.into_iter().enumerate() {
+            unique_ranks.insert(token.clone(), rank as Rank);
+            builder.push(token, rank as Rank);
+        }
+        let vocab = builder.build().unwrap();
+
+        for _ in 0..2000 {
+            let piece: Vec<u8> = (0..1 + next(40)).map(|_| alphabet[next(3)]).collect();
+            check(&piece, &ranks, &ranks);
+            check(&piece, &vocab, &unique_ranks);
+        }
+        check(&spaces, &ranks, &ranks);
+        check(&spaces, &vocab, &unique_ranks);
+    }
+
+    #[test]
+    fn parse_bfe_reports_line_errors() {
+        assert_eq!(
+            CoreBPEConstructor::parse_bfe("YQ== 0\nYg==").err(),
+            Some(ConstructionError::MalformedLine { line: 2 })
+        );
+        assert!(matches!(
+            CoreBPEConstructor::parse_bfe("YQ== 0\n!!! 1"),
+            Err(ConstructionError::InvalidBase64 { line: 2, .. })
+        ));
+        assert_eq!(
+            CoreBPEConstructor::parse_bfe("YQ== -1").err(),
+            Some(ConstructionError::InvalidRank {
+                line: 1,
+                rank: String::from("-1")
+            })
+        );
+        assert_eq!(
+            CoreBPEConstructor::parse_bfe("YQ== 0\n\nYg== 0").err(),
+            Some(ConstructionError::DuplicateRank { line: 3, rank: 0 })
+        );
+        assert_eq!(
+            CoreBPEConstructor::parse_bfe("YQ== 4294967295").err(),
+            Some(ConstructionError::InvalidRank {
+                line: 1,
+                rank: String::from("4294967295")
+            })
+        );
+    }
+
+    #[test]
+    fn sparse_ranks_are_rejected() {
+        // A single huge rank must not size the rank-indexed tables
+        assert_eq!(
+            CoreBPE::new("YQ== 0\nYg== 4000000000", HashMap::default(), "\\s+").err(),
+            Some(ConstructionError::RankOutOfRange {
+                rank: 4_000_000_000,
+                n_tokens: 2
+            })
+        );
+        let ranks = |rank| {
+            (0..=u8::MAX)
+                .map(|byte| (vec![byte], Rank::from(byte)))
+                .chain([(b"ab".to_vec(), rank)])
+        };
+        assert!(CoreBPE::from_ranks(ranks(70_000), vec![], "\\s+").is_err());
+        assert!(CoreBPE::from_ranks(ranks(60_000), vec![], "\\s+").is_ok());
+    }
+
+    #[test]
+    fn missing_single_bytes_are_rejected() {
+        // Encoding a piece containing "c" would have no rank to start from
+        let ranks = (0..=u8::MAX)
+            .filter(|&byte| byte != b'c')
+            .map(|byte| (vec![byte], Rank::from(byte)));
+        assert_eq!(
+            CoreBPE::from_ranks(ranks, vec![], "\\s+").err(),
+            Some(ConstructionError::InvalidVocabulary {
+                issues: vec![ValidationIssue::MissingSingleByteTokens { bytes: vec![b'c'] }]
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_pattern_reports_position() {
+        match CoreBPE::new("YQ== 0", HashMap::default(), "a(b") {
+            Err(ConstructionError::InvalidPattern { position, .. }) => assert!(position.is_some()),
+            _ => panic!("expected an invalid pattern error"),
+        }
+    }
+
+    #[test]
+    fn special_tokens_are_validated() {
+        assert_eq!(
+            CoreBPEConstructor::parse_special_tokens(serde_json::Value::Null).unwrap(),
+            HashMap::default()
+        );
+        assert_eq!(
+            CoreBPEConstructor::parse_special_tokens(serde_json::json!(["<|endoftext|>"])).err(),
+            Some(ConstructionError::SpecialTokensNotAnObject)
+        );
+        assert_eq!(
+            CoreBPEConstructor::parse_special_tokens(serde_json::json!({
+                "<|im_start|>": "100264",
+                "<|im_end|>": -1,
+                "<|im_sep|>": 1.5,
+                "": 3,
+                "<|big|>": 4294967296u64,
+                "<|max|>": 4294967295u64,
+                "<|ok|>": 4294967294u64,
+            }))
+            .err(),
+            Some(ConstructionError::InvalidSpecialTokens {
+                tokens: vec![
+                    String::from(""),
+                    String::from("<|big|>"),
+                    String::from("<|im_end|>"),
+                    String::from("<|im_sep|>"),
+                    String::from("<|im_start|>"),
+                    String::from("<|max|>"),
+                ]
+            })
+        );
+
+        // `max_token_value + 1` must not overflow, even on 32-bit targets
+        let mut special_tokens = HashMap::default();
+        special_tokens.insert(String::from("<|max|>"), Rank::MAX);
+        assert_eq!(
+            CoreBPE::new(&byte_ranks(&[]), special_tokens, "\\s+").err(),
+            Some(ConstructionError::InvalidSpecialTokens {
+                tokens: vec![String::from("<|max|>")]
+            })
+        );
+    }
+
+    #[test]
+    fn special_token_ids_must_not_collide() {
+        let ranks = "YQ== 0\nYg== 1";
+        let mut special_tokens = HashMap::default();
+        special_tokens.insert(String::from("<|endoftext|>"), 1);
+        assert_eq!(
+            CoreBPE::new(ranks, special_tokens.clone(), "\\s+").err(),
+            Some(ConstructionError::SpecialTokenRankCollision {
+                token: String::from("<|endoftext|>"),
+                rank: 1
+            })
+        );
+
+        special_tokens.insert(String::from("<|endoftext|>"), 2);
+        special_tokens.insert(String::from("<|eot|>"), 2);
+        assert_eq!(
+            CoreBPE::new(ranks, special_tokens, "\\s+").err(),
+            Some(ConstructionError::DuplicateSpecialTokenId {
+                first: String::from("<|endoftext|>"),
+                second: String::from("<|eot|>"),
+                rank: 2
+            })
+        );
+    }
+
+    #[test]
+    fn derived_encodings_share_the_vocabulary() {
+        let mut special_tokens = HashMap::default();
+        special_tokens.insert(String::from("<|endoftext|>"), 257);
+        let bpe = CoreBPE::new(&byte_ranks(&["ab"]), special_tokens, "[a-z]+").unwrap();
+
+        let mut chat_tokens = bpe.special_tokens_encoder.clone();
+        chat_tokens.insert(String::from("<|im_start|>"), 258);
+        let chat = bpe.with_special_tokens(chat_tokens).unwrap();
+        assert!(Arc::ptr_eq(&bpe.vocab, &chat.vocab));
+        assert_eq!(chat.max_token_value, 258);
+        assert_eq!(
+            chat.encode("ab<|im_start|>", &["<|im_start|>"].into_iter().collect()),
+            vec![256, 258]
+        );
+
+        let split = bpe.with_pattern("[a-z]").unwrap();
+        assert!(Arc::ptr_eq(&bpe.vocab, &split.vocab));
+        assert_eq!(split.encode_ordinary("ab"), vec![97, 98]);
+        assert_eq!(split.pattern, "[a-z]");
+        assert!(bpe.with_pattern("(").is_err());
+    }
+
+    #[test]
+    fn validate_bfe_reports_duplicates_without_caching() {
+        let ranks = "YQ== 0\nYg== 0\nYWI= 1";
+        let report = CoreBPEConstructor::validate_bfe(ranks, &HashMap::default(), None).unwrap();
+        assert!(report
+            .issues
+            .contains(&ValidationIssue::DuplicateRank { rank: 0, count: 2 }));
+        assert!(!VOCAB_CACHE
+            .lock()
+            .unwrap()
+            .contains_key(&VocabKey::content(ranks)));
+
+        assert_eq!(
+            CoreBPEConstructor::validate_bfe("YQ== 0\nYg==", &HashMap::default(), None).err(),
+            Some(ConstructionError::MalformedLine { line: 2 })
+        );
+    }
+
+    #[test]
+    fn validate_reports_vocabulary_issues() {
+        let tokens: Vec<(&[u8], Rank)> = vec![(b"a", 0), (b"b", 1), (b"ab", 3), (b"ba", 1)];
+        let mut special_tokens = HashMap::default();
+        special_tokens.insert(String::from("<|endoftext|>"), 6);
+
+        let report = validate_vocabulary(tokens.iter().copied(), &special_tokens, Some(6));
+        assert_eq!(report.n_tokens, 5);
+        assert_eq!(report.max_token_value, 6);
+        assert_eq!(report.n_vocab, 7);
+        assert!(!report.is_valid());
+
+        let missing_bytes: Vec<u8> = (0..=u8::MAX).filter(|b| *b != b'a' && *b != b'b').collect();
+        assert_eq!(
+            report.issues,
+            vec![
+                ValidationIssue::DuplicateRank { rank: 1, count: 2 },
+                ValidationIssue::MissingSingleByteTokens {
+                    bytes: missing_bytes
+                },
+                ValidationIssue::RankGap { start: 2, end: 3 },
+                ValidationIssue::RankGap { start: 4, end: 6 },
+                ValidationIssue::UnexpectedTokenCount {
+                    expected: 6,
+                    actual: 5
+                },
+                ValidationIssue::UnexpectedMaxTokenValue {
+                    expected: 5,
+                    actual: 6
+                },
+            ]
+        );
+
+        let mut builder = VocabBuilder::default();
+        for (token, rank) in tokens {
+            builder.push(token, rank);
+        }
+        assert_eq!(
+            build_vocab(builder).err(),
+            Some(ConstructionError::InvalidVocabulary {
+                issues: vec![ValidationIssue::DuplicateRank { rank: 1, count: 2 }]
+            })
+        );
+    }
+
+    #[test]
+    fn vocabularies_are_cached_by_content() {
+        let ranks = "YQ== 0\nYg== 1\nYw== 2\nYWJj 3";
+        let first = CoreBPEConstructor::new(ranks, None, "\\s+").unwrap();
+        let second = CoreBPEConstructor::new(ranks, None, "[a-z]+").unwrap();
+        assert!(Arc::ptr_eq(&first.vocab, &second.vocab));
+
+        let other = CoreBPEConstructor::new("YQ== 0\nYg== 1\nYw== 2", None, "\\s+").unwrap();
+        assert!(!Arc::ptr_eq(&first.vocab, &other.vocab));
+
+        clear_encoding_cache();
+        let third = CoreBPEConstructor::new(ranks, None, "\\s+").unwrap();
+        assert!(!Arc::ptr_eq(&first.vocab, &third.vocab));
+        assert!(first.vocab.iter().eq(third.vocab.iter()));
+    }
+
+    #[test]
+    fn colliding_cache_keys_load_their_own_vocabulary() {
+        let ranks = "YQ== 0\nYg== 1\nYw== 2\nYWI= 3";
+        let other = CoreBPEConstructor::parse_bfe("eA== 0\neQ== 1").unwrap();
+        VOCAB_CACHE.lock().unwrap().insert(
+            VocabKey::content(ranks),
+            CachedVocab {
+                source: Box::from(&b"eA== 0\neQ== 1"[..]),
+                vocab: Arc::new(other),
+            },
+        );
+
+        let constructor = CoreBPEConstructor::new(ranks, None, "\\s+").unwrap();
+        assert_eq!(constructor.vocab.get(3), Some(&b"ab"[..]));
+    }
+
+    #[test]
+    fn binary_vocabularies_round_trip() {
+        let mut special_tokens = HashMap::default();
+        special_tokens.insert("<|end|>".to_string(), 5);
+        let vocab = CoreBPEConstructor::parse_bfe("YQ== 0\nYg== 1\nYWI= 3").unwrap();
+        let data = binary::write(Some("ab"), "[a-z]+", &special_tokens, &vocab);
+
+        let encoding = binary::read(&data).unwrap();
+        assert_eq!(encoding.name.as_deref(), Some("ab"));
+        assert_eq!(encoding.pattern, "[a-z]+");
+        assert_eq!(encoding.special_tokens, special_tokens);
+        let loaded = binary::parse_tokens(encoding.tokens, encoding.tokens_offset).unwrap();
+        assert!(vocab.iter().eq(loaded.iter()));
+
+        // Errors point at the field that could not be read, here the pattern and the last token
+        assert_eq!(
+            binary::read(&data[..14]).err(),
+            Some(ConstructionError::MalformedBinary { offset: 8 })
+        );
+        let encoding = binary::read(&data[..data.len() - 1]).unwrap();
+        assert_eq!(
+            binary::parse_tokens(encoding.tokens, encoding.tokens_offset).err(),
+            Some(ConstructionError::MalformedBinary { offset: 31 })
+        );
+
+        let mut newer = data;
+        newer[4] = 2;
+        assert_eq!(
+            binary::read(&newer).err(),
+            Some(ConstructionError::UnsupportedBinaryVersion { version: 2 })
+        );
+    }
+
+    #[test]
+    fn snapshots_round_trip() {
+        let mut special_tokens = HashMap::default();
+        special_tokens.insert("<|end|>".to_string(), 257);
+        let bpe = CoreBPE::new(&byte_ranks(&["ab"]), special_tokens, "[a-z]+").unwrap();
+        let data = snapshot::write(
+            Some("ab"),
+            &bpe.pattern,
+            &bpe.special_tokens_encoder,
+            &bpe.vocab,
+        );
+
+        let restored = snapshot::read(&data).unwrap();
+        assert_eq!(restored.name.as_deref(), Some("ab"));
+        assert_eq!(restored.pattern, "[a-z]+");
+        assert_eq!(restored.special_tokens, bpe.special_tokens_encoder);
+        let vocab = Vocab::from_bytes(Cow::Owned(restored.tables.to_vec())).unwrap();
+        assert!(vocab.iter().eq(bpe.vocab.iter()));
+
+        let mut corrupted = data.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            snapshot::read(&corrupted).err(),
+            Some(ConstructionError::SnapshotChecksumMismatch)
+        );
+        let mut newer = data;
+        newer[4] = 2;
+        assert_eq!(
+            snapshot::read(&newer).err(),
+            Some(ConstructionError::UnsupportedSnapshotVersion { version: 2 })
+        );
+    }
+
+    #[test]
+    fn snapshots_do_not_reuse_colliding_cache_entries() {
+        let bpe = CoreBPE::new(&byte_ranks(&["ab"]), HashMap::default(), "[a-z]+").unwrap();
+        let data = snapshot::write(None, &bpe.pattern, &HashMap::default(), &bpe.vocab);
+        let tables = snapshot::read(&data).unwrap().tables;
+        let other = CoreBPEConstructor::parse_bfe("eA== 0\neQ== 1").unwrap();
+        VOCAB_CACHE.lock().unwrap().insert(
+            VocabKey::tables(tables),
+            CachedVocab {
+                source: other.to_bytes().into(),
+                vocab: Arc::new(other),
+            },
+        );
+
+        let restored = Encoding::deserialize(&data).unwrap();
+        assert_eq!(restored.decode_bytes(&[256]).unwrap(), b"ab");
+    }
+
+    #[test]
+    fn exported_ranks_parse_back() {
+        let ranks = "YWI= 3\nYQ== 0\n\nYg== 1\n";
+        let vocab = CoreBPEConstructor::parse_bfe(ranks).unwrap();
+        let exported = CoreBPEConstructor::export_bfe(&vocab);
+        assert_eq!(exported, "YQ== 0\nYg== 1\nYWI= 3\n");
+        assert!(CoreBPEConstructor::parse_bfe(&exported)
+            .unwrap()
+            .iter()
+            .eq(vocab.iter()));
+    }
+
+    #[test]
+    fn imports_huggingface_tokenizers() {
+        let tokenizer = |normalizer: &str, merges: &str| {
+            format!(
+                r#"{{
+                    "added_tokens": [
+                        {{"id": 5, "content": "<|end|>", "special": true}},
+                        {{"id": 3, "content": "ab", "special": false}}
+                    ],
+                    "normalizer": {},
+                    "pre_tokenizer": {{
+                        "type": "Sequence",
+                        "pretokenizers": [
+                            {{"type": "Split", "pattern": {{"Regex": " ?[a-z]+"}}, "behavior": "Isolated", "invert": false}},
+                            {{"type": "ByteLevel", "add_prefix_space": false, "use_regex": false}}
+                        ]
+                    }},
+                    "model": {{
+                        "type": "BPE",
+                        "vocab": {{"a": 0, "b": 1, "Ġ": 2, "ab": 3, "Ġab": 4, "<|end|>": 5}},
+                        "merges": {}
+                    }}
+                }}"#,
+                normalizer, merges
+            )
+        };
+
+        let import = huggingface::import(&tokenizer("null", r#"["a b", ["Ġ", "ab"]]"#)).unwrap();
+        assert_eq!(import.pattern, " ?[a-z]+");
+        assert_eq!(import.special_tokens.len(), 1);
+        assert_eq!(import.special_tokens["<|end|>"], 5);
+        let tokens: Vec<(&[u8], Rank)> = import.vocab.iter().collect();
+        assert_eq!(
+            tokens,
+            [
+                (&b"a"[..], 0),
+                (b"b", 1),
+                (b" ", 2),
+                (b"ab", 3),
+                (b" ab", 4)
+            ]
+        );
+
+        let padded = tokenizer("null", "[]").replace(
+            r#"{"id": 3, "content": "ab", "special": false}"#,
+            r#"{"id": 6, "content": "<pad>", "special": false}"#,
+        );
+        assert_eq!(
+            huggingface::import(&padded).err(),
+            Some(ConstructionError::UnsupportedTokenizer {
+                message:
+                    "added token \"<pad>\" that is neither special nor in the model vocabulary"
+                        .to_string()
+            })
+        );
+        assert_eq!(
+            huggingface::import(&tokenizer(r#"{"type": "NFC"}"#, "[]")).err(),
+            Some(ConstructionError::UnsupportedNormalizer {
+                normalizer: "NFC".to_string()
+            })
+        );
+        assert_eq!(
+            huggingface::import(&tokenizer("null", r#"["Ġ ab", "a b"]"#)).err(),
+            Some(ConstructionError::MergeOrderMismatch {
+                merge: 2,
+                token: "ab".to_string(),
+                id: 3
+            })
+        );
+    }
+
+    #[test]
+    fn huggingface_exports_import_back() {
+        let mut special_tokens = HashMap::default();
+        special_tokens.insert("<|end|>".to_string(), 259);
+        let bpe = CoreBPE::new(
+            &byte_ranks(&["ab", " ab", "abb"]),
+            special_tokens,
+            " ?[a-z]+",
+        )
+        .unwrap();
+
+        let exported =
+            huggingface::export(&bpe.vocab, &bpe.special_tokens_encoder, &bpe.pattern).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&exported).unwrap();
+        // The vocabulary is written in rank order, not sorted by token
+        let vocab_start = exported.find(r#""vocab":{"#).unwrap();
+        let positions: Vec<usize> = ["Ġ", "a", "b", "ab", "Ġab", "abb"]
+            .iter()
+            .map(|token| {
+                vocab_start
+                    + exported[vocab_start..]
+                        .find(&format!("\"{}\":", token))
+                        .unwrap()
+            })
+            .collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(
+            json["model"]["merges"],
+            serde_json::json!(["a b", "Ġ ab", "ab b"])
+        );
+        assert_eq!(json["added_tokens"][0]["content"], "<|end|>");
+
+        let import = huggingface::import(&exported).unwrap();
+        assert_eq!(import.pattern, bpe.pattern);
+        assert_eq!(import.special_tokens, bpe.special_tokens_encoder);
+        assert!(import.vocab.iter().eq(bpe.vocab.iter()));
+
+        // "ba" can only be formed from "b" and "a" at a higher rank than itself
+        let ranks = (0..=u8::MAX)
+            .map(|byte| (vec![byte], Rank::from(byte) + 1))
+            .chain([(b"ba".to_vec(), 0)]);
+        let unmergeable = CoreBPE::from_ranks(ranks, vec![], "").unwrap();
+        assert_eq!(
+            huggingface::export(&unmergeable.vocab, &HashMap::default(), "").err(),
+            Some(Error::UnmergeableToken { rank: 0 })
+        );
+    }
+
+    #[cfg(feature = "cl100k_base")]
+    #[test]
+    fn embedded_vocabularies_load() {
+        let encoding = Encoding::get("cl100k_base").unwrap();
+        // Also checks that every single byte is a token
+        assert!(encoding.validate(None).is_valid());
+    }
+
+    #[test]
+    fn max_token_value_covers_special_tokens() {
+        let ranks = &byte_ranks(&[]);
+        let bpe = CoreBPE::new(ranks, HashMap::default(), "\\s+").unwrap();
+        assert_eq!(bpe.max_token_value, 255);
+        assert_eq!(bpe.pattern, "\\s+");
+
+        let mut special_tokens = HashMap::default();
+        special_tokens.insert(String::from("<|endoftext|>"), 300);
+        let bpe = CoreBPE::new(ranks, special_tokens, "\\s+").unwrap();
+        assert_eq!(bpe.max_token_value, 300);
+    }
+
+    #[test]
+    fn encoding_checks_special_tokens() {
+        let special_tokens = vec![
+            (String::from("<|endoftext|>"), 257),
+            (String::from("<|im_start|>"), 258),
+        ];
+        let enc = Encoding::new(&byte_ranks(&["ab"]), special_tokens, "[a-z]+").unwrap();
+        let text = "ab<|im_start|>";
+
+        assert_eq!(
+            enc.encode(text, AllowedSpecial::None, DisallowedSpecial::All),
+            Err(Error::DisallowedSpecialToken {
+                token: String::from("<|im_start|>")
+            })
+        );
+        assert_eq!(
+            enc.encode(text, AllowedSpecial::All, DisallowedSpecial::All),
+            Ok(vec![256, 258])
+        );
+        let allowed = AllowedSpecial::Tokens(&["<|im_start|>"]);
+        let disallowed = DisallowedSpecial::Tokens(&["<|endoftext|>"]);
+        assert_eq!(enc.encode(text, allowed, disallowed), Ok(vec![256, 258]));
+        assert!(enc.encode("ab<|endoftext|>", allowed, disallowed).is_err());
+
+        assert_eq!(enc.decode_bytes(&[256, 258]).unwrap(), text.as_bytes());
+        assert_eq!(
+            enc.decode_bytes(&[259]),
+            Err(Error::UnknownToken { token: 259 })
+        );
+        assert_eq!(enc.encode_single_token(b"<|endoftext|>"), Ok(257));
+        assert_eq!(
+            enc.without_special_tokens(&["<|fim_prefix|>"]).err(),
+            Some(Error::UnknownSpecialToken {
+                token: String::from("<|fim_prefix|>")
+            })
+        );
+    }
 }
@@ -0,0 +1,330 @@
+//! The Node-API addon, with the same `Tiktoken` class and functions as the wasm bindings in
+//! `wasm.rs`, so that the `.d.ts` of the wasm package describes both. Strings and arrays are read
+//! from the JS heap directly instead of being copied into wasm memory.
+
+// `#[napi]` only registers the exported functions outside of tests
+#![cfg_attr(test, allow(dead_code))]
+
+use napi::bindgen_prelude::{Uint32Array, Uint8Array};
+use napi::Result;
+use napi_derive::napi;
+use rustc_hash::FxHashMap as HashMap;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::encoding::SpecialParams;
+use crate::{CoreBPE, CoreBPEConstructor, Encoding, Rank};
+
+#[napi]
+pub struct Tiktoken {
+    encoding: Encoding,
+}
+
+#[napi]
+impl Tiktoken {
+    #[napi(constructor)]
+    pub fn new(
+        tiktoken_bfe: String,
+        special_tokens: Option<Value>,
+        pat_str: String,
+    ) -> Result<Self> {
+        Ok(Tiktoken::from(
+            Encoding::new(
+                &tiktoken_bfe,
+                special_tokens_from_js(special_tokens)?,
+                &pat_str,
+            )
+            .map_err(js_error)?,
+        ))
+    }
+
+    #[napi(factory, js_name = "from_binary")]
+    pub fn from_binary(data: &[u8]) -> Result<Self> {
+        Ok(Tiktoken::from(
+            Encoding::from_binary(data).map_err(js_error)?,
+        ))
+    }
+
+    #[napi(factory, js_name = "from_huggingface")]
+    pub fn from_huggingface(tokenizer_json: String) -> Result<Self> {
+        Ok(Tiktoken::from(
+            Encoding::from_huggingface(&tokenizer_json).map_err(js_error)?,
+        ))
+    }
+
+    #[napi(factory, js_name = "from_gpt2")]
+    pub fn from_gpt2(encoder_json: String, vocab_bpe: String) -> Result<Self> {
+        Ok(Tiktoken::from(
+            Encoding::from_gpt2(&encoder_json, &vocab_bpe).map_err(js_error)?,
+        ))
+    }
+
+    #[napi]
+    pub fn serialize(&self) -> Uint8Array {
+        Uint8Array::new(self.encoding.serialize())
+    }
+
+    #[napi(factory)]
+    pub fn deserialize(data: &[u8]) -> Result<Self> {
+        Ok(Tiktoken::from(
+            Encoding::deserialize(data).map_err(js_error)?,
+        ))
+    }
+
+    #[napi(js_name = "with_special_tokens")]
+    pub fn with_special_tokens(&self, special_tokens: Option<Value>) -> Result<Tiktoken> {
+        Ok(Tiktoken::from(
+            self.encoding
+                .with_special_tokens(special_tokens_from_js(special_tokens)?)
+                .map_err(js_error)?,
+        ))
+    }
+
+    #[napi(js_name = "without_special_tokens")]
+    pub fn without_special_tokens(&self, tokens: Vec<String>) -> Result<Tiktoken> {
+        let tokens: Vec<&str> = tokens.iter().map(String::as_str).collect();
+        Ok(Tiktoken::from(
+            self.encoding
+                .without_special_tokens(&tokens)
+                .map_err(js_error)?,
+        ))
+    }
+
+    #[napi(js_name = "with_pattern")]
+    pub fn with_pattern(&self, pat_str: String) -> Result<Tiktoken> {
+        Ok(Tiktoken::from(
+            self.encoding.with_pattern(&pat_str).map_err(js_error)?,
+        ))
+    }
+
+    #[napi(js_name = "with_name")]
+    pub fn with_name(&self, name: String) -> Tiktoken {
+        Tiktoken::from(self.encoding.with_name(&name))
+    }
+
+    #[napi(getter)]
+    pub fn name(&self) -> Option<String> {
+        self.encoding.name().map(String::from)
+    }
+
+    #[napi(getter, js_name = "n_vocab")]
+    pub fn n_vocab(&self) -> u32 {
+        self.encoding.n_vocab() as u32
+    }
+
+    #[napi(getter, js_name = "max_token_value")]
+    pub fn max_token_value(&self) -> Rank {
+        self.encoding.max_token_value()
+    }
+
+    #[napi(getter, js_name = "eot_token")]
+    pub fn eot_token(&self) -> Option<Rank> {
+        self.encoding.eot_token()
+    }
+
+    #[napi(getter, js_name = "special_tokens")]
+    pub fn special_tokens(&self) -> Result<Value> {
+        to_js(&self.encoding.special_tokens())
+    }
+
+    #[napi(getter, js_name = "special_tokens_set")]
+    pub fn special_tokens_set(&self) -> Vec<String> {
+        self.encoding
+            .special_tokens()
+            .into_keys()
+            .map(String::from)
+            .collect()
+    }
+
+    #[napi(getter, js_name = "pat_str")]
+    pub fn pat_str(&self) -> String {
+        String::from(self.encoding.pattern())
+    }
+
+    #[napi(js_name = "export_ranks")]
+    pub fn export_ranks(&self) -> String {
+        self.encoding.export_ranks()
+    }
+
+    #[napi(js_name = "export_huggingface")]
+    pub fn export_huggingface(&self) -> Result<String> {
+        self.encoding.export_huggingface().map_err(js_error)
+    }
+
+    #[napi(js_name = "export_config")]
+    pub fn export_config(&self) -> String {
+        self.encoding.export_config()
+    }
+
+    #[napi]
+    pub fn encode(
+        &self,
+        text: String,
+        allowed_special: Option<Value>,
+        disallowed_special: Option<Value>,
+    ) -> Result<Uint32Array> {
+        let tokens = SpecialParams::from_napi(allowed_special, disallowed_special)?
+            .apply(|allowed, disallowed| self.encoding.encode(&text, allowed, disallowed))
+            .map_err(js_error)?;
+        Ok(Uint32Array::new(tokens))
+    }
+
+    #[napi(js_name = "encode_ordinary")]
+    pub fn encode_ordinary(&self, text: String) -> Uint32Array {
+        Uint32Array::new(self.encoding.encode_ordinary(&text))
+    }
+
+    #[napi(js_name = "encode_with_unstable")]
+    pub fn encode_with_unstable(
+        &self,
+        text: String,
+        allowed_special: Option<Value>,
+        disallowed_special: Option<Value>,
+    ) -> Result<Value> {
+        let result = SpecialParams::from_napi(allowed_special, disallowed_special)?
+            .apply(|allowed, disallowed| {
+                self.encoding
+                    .encode_with_unstable(&text, allowed, disallowed)
+            })
+            .map_err(js_error)?;
+        to_js(&result)
+    }
+
+    #[napi(js_name = "encode_single_token")]
+    pub fn encode_single_token(&self, bytes: &[u8]) -> Result<Rank> {
+        self.encoding.encode_single_token(bytes).map_err(js_error)
+    }
+
+    #[napi(js_name = "_encode_single_piece", skip_typescript)]
+    pub fn encode_single_piece(&self, bytes: &[u8]) -> Uint32Array {
+        Uint32Array::new(self.encoding.core().encode_single_piece(bytes))
+    }
+
+    #[napi]
+    pub fn decode(&self, tokens: &[u32]) -> Result<Uint8Array> {
+        let bytes = self.encoding.decode_bytes(tokens).map_err(js_error)?;
+        Ok(Uint8Array::new(bytes))
+    }
+
+    #[napi(js_name = "decode_single_token_bytes")]
+    pub fn decode_single_token_bytes(&self, token: Rank) -> Result<Uint8Array> {
+        let bytes = self
+            .encoding
+            .decode_single_token_bytes(token)
+            .map_err(js_error)?;
+        Ok(Uint8Array::new(bytes))
+    }
+
+    #[napi(js_name = "token_byte_values")]
+    pub fn token_byte_values(&self) -> Result<Value> {
+        to_js(&self.encoding.token_byte_values())
+    }
+
+    /// Does nothing, since the garbage collector frees the addon's objects. Kept so that code
+    /// written for the wasm build, which must free its objects, runs unchanged.
+    #[napi]
+    pub fn free(&self) {}
+}
+
+impl From<Encoding> for Tiktoken {
+    fn from(encoding: Encoding) -> Self {
+        Tiktoken { encoding }
+    }
+}
+
+fn js_error(error: impl std::fmt::Display) -> napi::Error {
+    napi::Error::from_reason(error.to_string())
+}
+
+fn to_js(value: &impl Serialize) -> Result<Value> {
+    serde_json::to_value(value).map_err(js_error)
+}
+
+fn special_tokens_from_js(value: Option<Value>) -> Result<HashMap<String, Rank>> {
+    CoreBPEConstructor::parse_special_tokens(value.unwrap_or(Value::Null)).map_err(js_error)
+}
+
+impl SpecialParams {
+    fn from_napi(
+        allowed_special: Option<Value>,
+        disallowed_special: Option<Value>,
+    ) -> Result<Self> {
+        let allowed = match allowed_special {
+            Some(Value::String(value)) if value == "all" => None,
+            Some(Value::String(_)) => {
+                return Err(js_error("Invalid value for allowed_special"));
+            }
+            Some(value) => Some(serde_json::from_value(value).unwrap_or_default()),
+            None => Some(vec![]),
+        };
+
+        let disallowed = match disallowed_special {
+            Some(Value::String(value)) if value == "all" => None,
+            Some(Value::String(_)) => {
+                return Err(js_error("Invalid value for disallowed_special"));
+            }
+            Some(value) => serde_json::from_value(value).ok(),
+            None => None,
+        };
+
+        Ok(SpecialParams {
+            allowed,
+            disallowed,
+        })
+    }
+}
+
+#[napi(js_name = "validate_encoding")]
+pub fn validate_encoding(
+    tiktoken_bfe: String,
+    special_tokens: Option<Value>,
+    explicit_n_vocab: Option<u32>,
+) -> Result<Value> {
+    let report = CoreBPEConstructor::validate_bfe(
+        &tiktoken_bfe,
+        &special_tokens_from_js(special_tokens)?,
+        explicit_n_vocab.map(|n| n as usize),
+    )
+    .map_err(js_error)?;
+
+    to_js(&report)
+}
+
+#[napi(js_name = "tiktoken_to_binary")]
+pub fn tiktoken_to_binary(
+    tiktoken_bfe: String,
+    special_tokens: Option<Value>,
+    pat_str: String,
+    name: Option<String>,
+) -> Result<Uint8Array> {
+    let bpe = CoreBPE::new(
+        &tiktoken_bfe,
+        special_tokens_from_js(special_tokens)?,
+        &pat_str,
+    )
+    .map_err(js_error)?;
+    Ok(Uint8Array::new(Encoding::from_bpe(name, bpe).to_binary()))
+}
+
+#[napi(js_name = "clear_encoding_cache")]
+pub fn clear_encoding_cache() {
+    crate::clear_encoding_cache();
+}
+
+#[cfg(feature = "embedded")]
+#[napi(js_name = "get_encoding")]
+pub fn get_encoding(encoding: String, extend_special_tokens: Option<Value>) -> Result<Tiktoken> {
+    Ok(Tiktoken::from(
+        Encoding::embedded(&encoding, special_tokens_from_js(extend_special_tokens)?)
+            .map_err(js_error)?,
+    ))
+}
+
+#[cfg(feature = "embedded")]
+#[napi(js_name = "encoding_for_model")]
+pub fn encoding_for_model(model: String, extend_special_tokens: Option<Value>) -> Result<Tiktoken> {
+    Ok(Tiktoken::from(
+        Encoding::embedded_for_model(&model, special_tokens_from_js(extend_special_tokens)?)
+            .map_err(js_error)?,
+    ))
+}
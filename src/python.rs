@@ -0,0 +1,120 @@
+//! The `_tiktoken` extension module behind the Python package in `tiktoken/`. The special
+//! token checks live in `tiktoken/core.py`, so this exposes [`CoreBPE`] as is.
+
+use pyo3::exceptions::{PyKeyError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyList, PyTuple};
+use rustc_hash::FxHashMap as HashMap;
+use std::collections::HashSet;
+
+use crate::{CoreBPE, Error, Rank};
+
+#[pyclass(name = "CoreBPE")]
+struct PyCoreBPE {
+    bpe: CoreBPE,
+}
+
+fn key_error(error: Error) -> PyErr {
+    match error {
+        // The key is the bytes as Python sees them, not a list of ints
+        Error::NotASingleToken { bytes } => {
+            Python::with_gil(|py| PyKeyError::new_err(PyBytes::new_bound(py, &bytes).unbind()))
+        }
+        Error::UnknownToken { token } => PyKeyError::new_err(token.to_string()),
+        error => PyValueError::new_err(error.to_string()),
+    }
+}
+
+#[pymethods]
+impl PyCoreBPE {
+    #[new]
+    fn new(
+        encoder: HashMap<Vec<u8>, Rank>,
+        special_tokens_encoder: HashMap<String, Rank>,
+        pattern: &str,
+    ) -> PyResult<Self> {
+        let bpe = CoreBPE::from_ranks(encoder, special_tokens_encoder, pattern)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(PyCoreBPE { bpe })
+    }
+
+    // ====================
+    // Encoding
+    // ====================
+
+    fn encode_ordinary(&self, py: Python, text: &str) -> Vec<Rank> {
+        py.allow_threads(|| self.bpe.encode_ordinary(text))
+    }
+
+    fn encode(&self, py: Python, text: &str, allowed_special: HashSet<String>) -> Vec<Rank> {
+        let allowed_special = allowed_special.iter().map(String::as_str).collect();
+        py.allow_threads(|| self.bpe.encode(text, &allowed_special))
+    }
+
+    fn _encode_bytes(&self, py: Python, bytes: &[u8]) -> Vec<Rank> {
+        py.allow_threads(|| self.bpe._encode_bytes(bytes))
+    }
+
+    fn encode_with_unstable(
+        &self,
+        py: Python,
+        text: &str,
+        allowed_special: HashSet<String>,
+    ) -> Py<PyTuple> {
+        let allowed_special = allowed_special.iter().map(String::as_str).collect();
+        let (tokens, completions) =
+            py.allow_threads(|| self.bpe.encode_with_unstable(text, &allowed_special));
+        let py_completions = PyList::new_bound(
+            py,
+            completions
+                .iter()
+                .map(|seq| PyList::new_bound(py, &seq[..])),
+        );
+        (tokens, py_completions).into_py(py)
+    }
+
+    fn encode_single_token(&self, piece: &[u8]) -> PyResult<Rank> {
+        self.bpe.encode_single_token(piece).map_err(key_error)
+    }
+
+    fn encode_single_piece(&self, piece: &[u8]) -> Vec<Rank> {
+        self.bpe.encode_single_piece(piece)
+    }
+
+    // ====================
+    // Decoding
+    // ====================
+
+    fn decode_bytes(&self, py: Python, tokens: Vec<Rank>) -> PyResult<Py<PyBytes>> {
+        let bytes = py
+            .allow_threads(|| self.bpe.decode_bytes(&tokens))
+            .map_err(key_error)?;
+        Ok(PyBytes::new_bound(py, &bytes).unbind())
+    }
+
+    fn decode_single_token_bytes(&self, py: Python, token: Rank) -> PyResult<Py<PyBytes>> {
+        let bytes = self
+            .bpe
+            .decode_single_token_bytes(token)
+            .map_err(key_error)?;
+        Ok(PyBytes::new_bound(py, &bytes).unbind())
+    }
+
+    // ====================
+    // Miscellaneous
+    // ====================
+
+    fn token_byte_values(&self, py: Python) -> Vec<Py<PyBytes>> {
+        self.bpe
+            .token_byte_values()
+            .iter()
+            .map(|bytes| PyBytes::new_bound(py, bytes).unbind())
+            .collect()
+    }
+}
+
+#[pymodule]
+fn _tiktoken(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyCoreBPE>()?;
+    Ok(())
+}
@@ -0,0 +1,84 @@
+//! Snapshots of a built encoding, which restore without parsing ranks, inverting them or
+//! sorting tokens. Only the regexes are compiled again.
+//!
+//! ```text
+//! "TKSN", version: u32, checksum: u64, payload
+//! ```
+//!
+//! The payload is the metadata of the [binary format](crate::binary) followed by the tables of
+//! [`Vocab::to_bytes`]. The checksum is [`hash_token`] of the payload, and like the version
+//! is little-endian. It catches truncated or accidentally corrupted snapshots, but is not
+//! cryptographic: a snapshot crafted to match it is only as trustworthy as its source, and
+//! [`Vocab::from_bytes`] still validates the tables.
+
+use rustc_hash::FxHashMap as HashMap;
+
+use crate::binary::{read_metadata, write_metadata, Metadata, Reader};
+use crate::vocab::{hash_token, Rank, Vocab};
+use crate::ConstructionError;
+
+const MAGIC: &[u8; 4] = b"TKSN";
+
+/// The version written by [`write`]; [`read`] rejects any other.
+pub(crate) const VERSION: u32 = 1;
+
+const HEADER_LEN: usize = 16;
+
+pub(crate) struct Snapshot<'a> {
+    pub(crate) name: Option<String>,
+    pub(crate) pattern: String,
+    pub(crate) special_tokens: HashMap<String, Rank>,
+    /// Tables to load with [`Vocab::from_bytes`].
+    pub(crate) tables: &'a [u8],
+}
+
+pub(crate) fn write(
+    name: Option<&str>,
+    pattern: &str,
+    special_tokens: &HashMap<String, Rank>,
+    vocab: &Vocab,
+) -> Vec<u8> {
+    let mut payload = vec![];
+    write_metadata(&mut payload, name, pattern, special_tokens);
+    payload.extend_from_slice(&vocab.to_bytes());
+
+    let mut data = Vec::with_capacity(HEADER_LEN + payload.len());
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&VERSION.to_le_bytes());
+    data.extend_from_slice(&hash_token(&payload).to_le_bytes());
+    data.extend_from_slice(&payload);
+    data
+}
+
+pub(crate) fn read(data: &[u8]) -> Result<Snapshot<'_>, ConstructionError> {
+    if data.len() < HEADER_LEN || !data.starts_with(MAGIC) {
+        return Err(ConstructionError::MalformedSnapshot);
+    }
+
+    let mut version = [0; 4];
+    version.copy_from_slice(&data[4..8]);
+    let version = u32::from_le_bytes(version);
+    if version != VERSION {
+        return Err(ConstructionError::UnsupportedSnapshotVersion { version });
+    }
+
+    let mut checksum = [0; 8];
+    checksum.copy_from_slice(&data[8..HEADER_LEN]);
+    let payload = &data[HEADER_LEN..];
+    if u64::from_le_bytes(checksum) != hash_token(payload) {
+        return Err(ConstructionError::SnapshotChecksumMismatch);
+    }
+
+    let mut reader = Reader::new(payload);
+    let Metadata {
+        name,
+        pattern,
+        special_tokens,
+    } = read_metadata(&mut reader).map_err(|_| ConstructionError::MalformedSnapshot)?;
+    Ok(Snapshot {
+        name,
+        pattern,
+        special_tokens,
+        tables: reader.rest(),
+    })
+}
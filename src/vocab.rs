@@ -0,0 +1,649 @@
+//! Compact storage for the ordinary (mergeable) tokens of an encoding.
+//!
+//! Every token's bytes live in a single arena, concatenated in rank order. Decoding indexes a
+//! dense offset table by rank, encoding probes an open-addressing table of ranks that compares
+//! candidates against the arena, and the sorted order needed for unstable encoding is a
+//! permutation of ranks. Building a vocabulary therefore performs a handful of allocations
+//! regardless of the number of tokens.
+//!
+//! The byte pair merge looks pairs of tokens up by rank in a [`MergeTable`], which is derived
+//! from the vocabulary the first time a piece has to be merged, so that loading stays cheap and
+//! encodings only used for decoding never build it. Building it looks up every split of every
+//! token: for a byte-level vocabulary of 100k tokens that takes about 70 ms and 4 MiB, next to
+//! the 6 MiB of the vocabulary itself, and makes encoding around 10% faster afterwards. Servers
+//! that cannot afford the delay on their first request can encode a short text at startup.
+
+use once_cell::sync::OnceCell;
+use std::borrow::Cow;
+use std::ops::Range;
+
+/// Marks an unused slot in [`Vocab::table`].
+const EMPTY: u32 = u32::MAX;
+
+/// Identifies the output of [`Vocab::to_bytes`]. The last byte is the layout version.
+const MAGIC: &[u8; 4] = b"TKV\x01";
+
+/// How far the largest rank may exceed twice the number of tokens. Tables are indexed by rank,
+/// so ranks far beyond the token count would allocate memory for nothing.
+const RANK_SLACK: u64 = 1 << 16;
+
+/// Multiplier of the FxHash function, see `rustc_hash`.
+const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+/// Hashes token bytes eight at a time. Unlike `FxHasher` this does not depend on the target's
+/// word size, so tables built on the host are valid in wasm.
+pub(crate) fn hash_token(bytes: &[u8]) -> u64 {
+    let mut hash = (bytes.len() as u64).wrapping_mul(SEED);
+    let mut chunks = bytes.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut word = [0; 8];
+        word.copy_from_slice(chunk);
+        hash = (hash.rotate_left(5) ^ u64::from_le_bytes(word)).wrapping_mul(SEED);
+    }
+    let remainder = chunks.remainder();
+    if !remainder.is_empty() {
+        let mut word = [0; 8];
+        word[..remainder.len()].copy_from_slice(remainder);
+        hash = (hash.rotate_left(5) ^ u64::from_le_bytes(word)).wrapping_mul(SEED);
+    }
+    hash
+}
+
+pub(crate) fn write_varint(data: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        data.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+pub(crate) fn read_varint(data: &mut &[u8]) -> Option<u32> {
+    let mut value = 0u32;
+    for (index, &byte) in data.iter().enumerate().take(5) {
+        value |= u32::from(byte & 0x7f).checked_shl(7 * index as u32)?;
+        if byte < 0x80 {
+            *data = &data[index + 1..];
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// A token id. Ordinary tokens are identified by their rank, which is also their merge priority.
+pub type Rank = u32;
+
+/// Lookup from token bytes to rank, as needed by the byte pair merge.
+pub trait Ranks {
+    fn rank(&self, token: &[u8]) -> Option<Rank>;
+
+    /// The rank of `merged`, the bytes of the token `left` followed by those of the token
+    /// `right`. Implementations can look the pair up by rank rather than hashing the bytes.
+    fn merged_rank(&self, left: Rank, right: Rank, merged: &[u8]) -> Option<Rank> {
+        let _ = (left, right);
+        self.rank(merged)
+    }
+}
+
+impl<S: std::hash::BuildHasher> Ranks for std::collections::HashMap<Vec<u8>, Rank, S> {
+    fn rank(&self, token: &[u8]) -> Option<Rank> {
+        self.get(token).copied()
+    }
+}
+
+/// Why [`VocabBuilder::build`] rejected the tokens.
+#[derive(Debug)]
+pub(crate) enum BuildError {
+    /// Ranks used by more than one token, along with how many tokens use them.
+    DuplicateRanks(Vec<(Rank, usize)>),
+    /// The largest rank is `u32::MAX`, which marks empty slots, or far exceeds the number of
+    /// tokens.
+    RankOutOfRange { rank: Rank, n_tokens: usize },
+}
+
+/// Collects tokens in any order before laying them out in a [`Vocab`].
+#[derive(Default)]
+pub(crate) struct VocabBuilder {
+    bytes: Vec<u8>,
+    tokens: Vec<(Rank, Range<usize>)>,
+}
+
+impl VocabBuilder {
+    pub(crate) fn with_capacity(tokens: usize, bytes: usize) -> Self {
+        VocabBuilder {
+            bytes: Vec::with_capacity(bytes),
+            tokens: Vec::with_capacity(tokens),
+        }
+    }
+
+    pub(crate) fn push(&mut self, token: &[u8], rank: Rank) {
+        let start = self.bytes.len();
+        self.bytes.extend_from_slice(token);
+        self.tokens.push((rank, start..self.bytes.len()));
+    }
+
+    /// Appends a token whose bytes were written to the end of [`VocabBuilder::buffer`] since
+    /// `start`, avoiding an intermediate allocation when decoding.
+    pub(crate) fn push_written(&mut self, start: usize, rank: Rank) {
+        self.tokens.push((rank, start..self.bytes.len()));
+    }
+
+    pub(crate) fn buffer(&mut self) -> &mut Vec<u8> {
+        &mut self.bytes
+    }
+
+    /// Reads tokens written by [`Vocab::to_front_coded`], returning `None` if they are truncated
+    /// or inconsistent.
+    #[cfg_attr(not(feature = "compress"), allow(dead_code))]
+    pub(crate) fn from_front_coded(mut data: &[u8]) -> Option<Self> {
+        let count = read_varint(&mut data)? as usize;
+        let mut builder = VocabBuilder::with_capacity(count.min(data.len()), data.len() * 2);
+        let mut previous = 0..0;
+        for _ in 0..count {
+            let shared = read_varint(&mut data)? as usize;
+            let suffix = read_varint(&mut data)? as usize;
+            if shared > previous.len() || suffix > data.len() {
+                return None;
+            }
+
+            let start = builder.bytes.len();
+            builder
+                .bytes
+                .extend_from_within(previous.start..previous.start + shared);
+            builder.bytes.extend_from_slice(&data[..suffix]);
+            data = &data[suffix..];
+            builder.push_written(start, read_varint(&mut data)?);
+            previous = start..builder.bytes.len();
+        }
+
+        if data.is_empty() {
+            Some(builder)
+        } else {
+            None
+        }
+    }
+
+    /// Lays the tokens out in rank order, ignoring empty tokens.
+    pub(crate) fn build(mut self) -> Result<Vocab, BuildError> {
+        self.tokens.retain(|(_, range)| !range.is_empty());
+        self.tokens.sort_unstable_by_key(|(rank, _)| *rank);
+
+        if let Some(&(rank, _)) = self.tokens.last() {
+            let n_tokens = self.tokens.len();
+            if rank == Rank::MAX || u64::from(rank) >= 2 * n_tokens as u64 + RANK_SLACK {
+                return Err(BuildError::RankOutOfRange { rank, n_tokens });
+            }
+        }
+
+        let mut duplicates: Vec<(Rank, usize)> = vec![];
+        for pair in self.tokens.windows(2) {
+            if pair[0].0 == pair[1].0 {
+                match duplicates.last_mut() {
+                    Some((rank, count)) if *rank == pair[0].0 => *count += 1,
+                    _ => duplicates.push((pair[0].0, 2)),
+                }
+            }
+        }
+        if !duplicates.is_empty() {
+            return Err(BuildError::DuplicateRanks(duplicates));
+        }
+
+        let n_ranks = self.tokens.last().map_or(0, |(rank, _)| *rank as usize + 1);
+        let mut bytes = Vec::with_capacity(self.bytes.len());
+        let mut offsets = Vec::with_capacity(n_ranks + 1);
+        for (rank, range) in &self.tokens {
+            // Unused ranks are encoded as empty ranges
+            while offsets.len() < *rank as usize {
+                offsets.push(bytes.len() as u32);
+            }
+            offsets.push(bytes.len() as u32);
+            bytes.extend_from_slice(&self.bytes[range.clone()]);
+        }
+        offsets.push(bytes.len() as u32);
+
+        let present: Vec<u32> = self.tokens.iter().map(|(rank, _)| *rank).collect();
+        Ok(Vocab::from_parts(Cow::Owned(bytes), offsets, present))
+    }
+}
+
+pub(crate) struct Vocab {
+    /// The bytes of every token, concatenated in rank order. Borrowed when the vocabulary is
+    /// embedded in the binary.
+    bytes: Cow<'static, [u8]>,
+    /// `bytes[offsets[rank]..offsets[rank + 1]]` are the bytes of `rank`. Unused ranks have
+    /// empty ranges; empty tokens are not allowed.
+    offsets: Vec<u32>,
+    /// Open-addressing table of ranks, indexed by the top bits of [`hash_token`].
+    table: Vec<u32>,
+    shift: u32,
+    /// Ranks ordered by their token bytes.
+    sorted_ranks: Vec<u32>,
+    /// Built on first use rather than when loading, so that loading tables stays cheap.
+    merges: OnceCell<MergeTable>,
+}
+
+impl Vocab {
+    fn from_parts(bytes: Cow<'static, [u8]>, offsets: Vec<u32>, ranks: Vec<u32>) -> Self {
+        // Keep the load factor at or below one half
+        let bits = (ranks.len() * 2)
+            .max(2)
+            .next_power_of_two()
+            .trailing_zeros();
+        let mut vocab = Vocab {
+            bytes,
+            offsets,
+            table: vec![EMPTY; 1 << bits],
+            shift: 64 - bits,
+            sorted_ranks: ranks,
+            merges: OnceCell::new(),
+        };
+
+        for i in 0..vocab.sorted_ranks.len() {
+            let rank = vocab.sorted_ranks[i];
+            let mut slot = vocab.slot(vocab.token(rank));
+            while vocab.table[slot] != EMPTY {
+                slot = (slot + 1) & (vocab.table.len() - 1);
+            }
+            vocab.table[slot] = rank;
+        }
+
+        let mut sorted_ranks = std::mem::take(&mut vocab.sorted_ranks);
+        sorted_ranks.sort_unstable_by(|a, b| vocab.token(*a).cmp(vocab.token(*b)));
+        vocab.sorted_ranks = sorted_ranks;
+        vocab
+    }
+
+    fn slot(&self, token: &[u8]) -> usize {
+        (hash_token(token) >> self.shift) as usize
+    }
+
+    fn token(&self, rank: u32) -> &[u8] {
+        let rank = rank as usize;
+        &self.bytes[self.offsets[rank] as usize..self.offsets[rank + 1] as usize]
+    }
+
+    /// Serializes the laid-out tables, so that they can be loaded again without hashing or
+    /// sorting. All integers are little-endian `u32`s:
+    ///
+    /// ```text
+    /// magic, offsets.len(), table.len(), sorted_ranks.len(), bytes.len(),
+    /// offsets, table, sorted_ranks, bytes
+    /// ```
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(
+            20 + 4 * (self.offsets.len() + self.table.len() + self.sorted_ranks.len())
+                + self.bytes.len(),
+        );
+        data.extend_from_slice(MAGIC);
+        for len in [
+            self.offsets.len(),
+            self.table.len(),
+            self.sorted_ranks.len(),
+            self.bytes.len(),
+        ] {
+            data.extend_from_slice(&(len as u32).to_le_bytes());
+        }
+        for table in [&self.offsets, &self.table, &self.sorted_ranks] {
+            for value in table.iter() {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        data.extend_from_slice(&self.bytes);
+        data
+    }
+
+    /// Serializes just the tokens, in a form that compresses well: in byte order, each token is
+    /// the length of the prefix it shares with the previous token, the length of the rest, the
+    /// rest of its bytes and its rank. The token count and all lengths and ranks are LEB128.
+    #[allow(dead_code)] // Used by build.rs
+    pub(crate) fn to_front_coded(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.bytes.len() + 4 * self.len());
+        write_varint(&mut data, self.len() as u32);
+        let mut previous: &[u8] = &[];
+        for token in self.sorted_tokens() {
+            let shared = previous
+                .iter()
+                .zip(token)
+                .take_while(|(a, b)| a == b)
+                .count();
+            write_varint(&mut data, shared as u32);
+            write_varint(&mut data, (token.len() - shared) as u32);
+            data.extend_from_slice(&token[shared..]);
+            write_varint(&mut data, self.rank(token).unwrap());
+            previous = token;
+        }
+        data
+    }
+
+    /// Loads tables written by [`Vocab::to_bytes`], returning `None` if they are truncated or
+    /// inconsistent. Borrowed token bytes are used in place rather than copied.
+    pub(crate) fn from_bytes(data: Cow<'static, [u8]>) -> Option<Self> {
+        fn read_u32s(data: &[u8], at: &mut usize, len: usize) -> Option<Vec<u32>> {
+            let end = at.checked_add(len.checked_mul(4)?)?;
+            let values = data
+                .get(*at..end)?
+                .chunks_exact(4)
+                .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                .collect();
+            *at = end;
+            Some(values)
+        }
+
+        if data.get(..4)? != MAGIC {
+            return None;
+        }
+        let mut at = 4;
+        let lens = read_u32s(&data, &mut at, 4)?;
+        let offsets = read_u32s(&data, &mut at, lens[0] as usize)?;
+        let table = read_u32s(&data, &mut at, lens[1] as usize)?;
+        let sorted_ranks = read_u32s(&data, &mut at, lens[2] as usize)?;
+        let bytes_len = lens[3] as usize;
+        if data.len() != at.checked_add(bytes_len)? {
+            return None;
+        }
+
+        // Everything below is guaranteed by `VocabBuilder::build`, check it so that lookups
+        // never go out of bounds
+        let n_ranks = offsets.len().checked_sub(1)?;
+        if n_ranks > Rank::MAX as usize
+            || offsets[0] != 0
+            || offsets[n_ranks] as usize != bytes_len
+            || offsets.windows(2).any(|pair| pair[0] > pair[1])
+            || !table.len().is_power_of_two()
+            || table.len() < 2 * sorted_ranks.len().max(1)
+            // Together with the size check, this leaves empty slots to end every probe
+            || table.iter().filter(|&&rank| rank != EMPTY).count() != sorted_ranks.len()
+            || table
+                .iter()
+                .any(|&rank| rank != EMPTY && rank as usize >= n_ranks)
+            || sorted_ranks.iter().any(|&rank| rank as usize >= n_ranks)
+        {
+            return None;
+        }
+
+        let bytes = match data {
+            Cow::Borrowed(data) => Cow::Borrowed(&data[at..]),
+            Cow::Owned(mut data) => Cow::Owned(data.split_off(at)),
+        };
+        Some(Vocab {
+            bytes,
+            offsets,
+            shift: 64 - table.len().trailing_zeros(),
+            table,
+            sorted_ranks,
+            merges: OnceCell::new(),
+        })
+    }
+
+    /// The number of tokens.
+    pub(crate) fn len(&self) -> usize {
+        self.sorted_ranks.len()
+    }
+
+    /// The largest rank, if there are any tokens.
+    pub(crate) fn max_rank(&self) -> Option<Rank> {
+        self.offsets.len().checked_sub(2).map(|rank| rank as Rank)
+    }
+
+    pub(crate) fn contains_rank(&self, rank: Rank) -> bool {
+        let rank = rank as usize;
+        rank + 1 < self.offsets.len() && self.offsets[rank] != self.offsets[rank + 1]
+    }
+
+    /// The bytes of the token with the given rank.
+    pub(crate) fn get(&self, rank: Rank) -> Option<&[u8]> {
+        if self.contains_rank(rank) {
+            Some(self.token(rank))
+        } else {
+            None
+        }
+    }
+
+    /// Tokens ordered by their bytes, for prefix searches.
+    pub(crate) fn sorted_tokens(&self) -> impl ExactSizeIterator<Item = &[u8]> + '_ {
+        self.sorted_ranks.iter().map(move |&rank| self.token(rank))
+    }
+
+    /// The `index`-th token in byte order, and its rank.
+    pub(crate) fn sorted_token(&self, index: usize) -> (&[u8], Rank) {
+        let rank = self.sorted_ranks[index];
+        (self.token(rank), rank)
+    }
+
+    /// The index of the first token in byte order that is not less than `prefix`.
+    pub(crate) fn sorted_partition_point(&self, prefix: &[u8]) -> usize {
+        self.sorted_ranks
+            .partition_point(|&rank| self.token(rank) < prefix)
+    }
+
+    /// Tokens and their ranks, in rank order.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&[u8], Rank)> + '_ {
+        (0..self.offsets.len().saturating_sub(1) as Rank)
+            .filter(move |&rank| self.contains_rank(rank))
+            .map(move |rank| (self.token(rank), rank))
+    }
+}
+
+impl Ranks for Vocab {
+    fn rank(&self, token: &[u8]) -> Option<Rank> {
+        let mask = self.table.len() - 1;
+        let mut slot = self.slot(token);
+        loop {
+            let rank = self.table[slot];
+            if rank == EMPTY {
+                return None;
+            }
+            if self.token(rank) == token {
+                return Some(rank);
+            }
+            slot = (slot + 1) & mask;
+        }
+    }
+
+    fn merged_rank(&self, left: Rank, right: Rank, _merged: &[u8]) -> Option<Rank> {
+        self.merges
+            .get_or_init(|| MergeTable::new(self))
+            .get(left, right)
+    }
+}
+
+/// Open-addressing table from pairs of ranks to the rank of their concatenation, with an entry
+/// for every way of splitting every token into two tokens. It is exact, so a missing pair does
+/// not form a token.
+struct MergeTable {
+    /// Pairs packed by [`MergeTable::key`], indexed by their FxHash scaled to the table length.
+    /// Twice as many slots as pairs keeps probes for missing pairs short without rounding the
+    /// length up to a power of two.
+    keys: Vec<u64>,
+    ranks: Vec<Rank>,
+}
+
+impl MergeTable {
+    /// Marks an unused slot in [`MergeTable::keys`]. Ranks never reach `u32::MAX`.
+    const EMPTY: u64 = u64::MAX;
+
+    fn new(vocab: &Vocab) -> Self {
+        let mut merges = vec![];
+        for (token, rank) in vocab.iter() {
+            // When tokens share their bytes, the bytes look up the rank the merge must produce
+            if vocab.rank(token) != Some(rank) {
+                continue;
+            }
+            for split in 1..token.len() {
+                if let Some(left) = vocab.rank(&token[..split]) {
+                    if let Some(right) = vocab.rank(&token[split..]) {
+                        merges.push((Self::key(left, right), rank));
+                    }
+                }
+            }
+        }
+
+        let len = merges.len() * 2 + 1;
+        let mut table = MergeTable {
+            keys: vec![Self::EMPTY; len],
+            ranks: vec![0; len],
+        };
+        for (key, rank) in merges {
+            let mut slot = table.slot(key);
+            while table.keys[slot] != Self::EMPTY {
+                slot = table.next(slot);
+            }
+            table.keys[slot] = key;
+            table.ranks[slot] = rank;
+        }
+        table
+    }
+
+    fn key(left: Rank, right: Rank) -> u64 {
+        u64::from(left) << 32 | u64::from(right)
+    }
+
+    fn slot(&self, key: u64) -> usize {
+        ((u128::from(key.wrapping_mul(SEED)) * self.keys.len() as u128) >> 64) as usize
+    }
+
+    fn next(&self, slot: usize) -> usize {
+        if slot + 1 == self.keys.len() {
+            0
+        } else {
+            slot + 1
+        }
+    }
+
+    fn get(&self, left: Rank, right: Rank) -> Option<Rank> {
+        let key = Self::key(left, right);
+        let mut slot = self.slot(key);
+        loop {
+            match self.keys[slot] {
+                Self::EMPTY => return None,
+                found if found == key => return Some(self.ranks[slot]),
+                _ => slot = self.next(slot),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BuildError, Ranks, Vocab, VocabBuilder};
+    use std::borrow::Cow;
+
+    #[test]
+    fn lays_out_tokens_by_rank() {
+        let mut builder = VocabBuilder::default();
+        builder.push(b"cd", 4);
+        builder.push(b"a", 0);
+        builder.push(b"b", 1);
+        builder.push(b"ab", 2);
+        let vocab = builder.build().unwrap();
+
+        assert_eq!(vocab.len(), 4);
+        assert_eq!(vocab.max_rank(), Some(4));
+        assert_eq!(vocab.get(2), Some(b"ab".as_slice()));
+        assert_eq!(vocab.get(3), None);
+        assert_eq!(vocab.get(5), None);
+        assert_eq!(vocab.rank(b"cd"), Some(4));
+        assert_eq!(vocab.rank(b"c"), None);
+        assert_eq!(
+            vocab.sorted_tokens().collect::<Vec<_>>(),
+            vec![b"a".as_slice(), b"ab", b"b", b"cd"]
+        );
+        assert_eq!(vocab.sorted_partition_point(b"b"), 2);
+        assert_eq!(
+            vocab.iter().collect::<Vec<_>>(),
+            vec![
+                (b"a".as_slice(), 0),
+                (b"b".as_slice(), 1),
+                (b"ab".as_slice(), 2),
+                (b"cd".as_slice(), 4)
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_duplicate_ranks() {
+        let mut builder = VocabBuilder::default();
+        builder.push(b"a", 0);
+        builder.push(b"b", 0);
+        builder.push(b"c", 0);
+        builder.push(b"d", 1);
+        assert!(matches!(
+            builder.build(),
+            Err(BuildError::DuplicateRanks(duplicates)) if duplicates == vec![(0, 3)]
+        ));
+    }
+
+    #[test]
+    fn rejects_ranks_beyond_the_tables() {
+        let mut builder = VocabBuilder::default();
+        builder.push(b"a", 0);
+        builder.push(b"b", 4_000_000_000);
+        assert!(matches!(
+            builder.build(),
+            Err(BuildError::RankOutOfRange {
+                rank: 4_000_000_000,
+                n_tokens: 2
+            })
+        ));
+
+        let mut builder = VocabBuilder::default();
+        builder.push(b"a", u32::MAX);
+        assert!(matches!(
+            builder.build(),
+            Err(BuildError::RankOutOfRange { .. })
+        ));
+    }
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let mut builder = VocabBuilder::default();
+        for (rank, token) in [&b"a"[..], b"b", b"ab", b"abc", b"bc"].iter().enumerate() {
+            builder.push(token, rank as u32 * 2);
+        }
+        let vocab = builder.build().unwrap();
+
+        let data = vocab.to_bytes();
+        let loaded = Vocab::from_bytes(Cow::Owned(data.clone())).unwrap();
+        assert!(vocab.iter().eq(loaded.iter()));
+        assert!(vocab.sorted_tokens().eq(loaded.sorted_tokens()));
+        assert_eq!(loaded.rank(b"abc"), Some(6));
+        assert_eq!(loaded.rank(b"ba"), None);
+
+        assert!(Vocab::from_bytes(Cow::Owned(data[..data.len() - 1].to_vec())).is_none());
+        let mut corrupted = data.clone();
+        corrupted[3] = 0;
+        assert!(Vocab::from_bytes(Cow::Owned(corrupted)).is_none());
+
+        // A table without empty slots would make every miss probe forever
+        let table_start = 20 + 4 * (vocab.max_rank().unwrap() as usize + 2);
+        let table_len = u32::from_le_bytes([data[8], data[9], data[10], data[11]]) as usize;
+        let mut full = data;
+        for slot in full[table_start..table_start + 4 * table_len].chunks_exact_mut(4) {
+            slot.copy_from_slice(&0u32.to_le_bytes());
+        }
+        assert!(Vocab::from_bytes(Cow::Owned(full)).is_none());
+    }
+
+    #[test]
+    fn round_trips_through_front_coding() {
+        let mut builder = VocabBuilder::default();
+        for (rank, token) in [&b"abc"[..], b"a", b"abd", b"b", b"ab", b"\xff\x00"]
+            .iter()
+            .enumerate()
+        {
+            builder.push(token, rank as u32 * 300);
+        }
+        let vocab = builder.build().unwrap();
+
+        let data = vocab.to_front_coded();
+        let loaded = VocabBuilder::from_front_coded(&data)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!(vocab.iter().eq(loaded.iter()));
+
+        assert!(VocabBuilder::from_front_coded(&data[..data.len() - 1]).is_none());
+        let mut trailing = data;
+        trailing.push(0);
+        assert!(VocabBuilder::from_front_coded(&trailing).is_none());
+    }
+}
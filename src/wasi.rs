@@ -0,0 +1,224 @@
+//! The WebAssembly component, which exports the `tokenizer` interface of `wit/tiktoken.wit`
+//! without any JavaScript glue.
+
+use crate::{AllowedSpecial, DisallowedSpecial, Encoding, Error};
+
+wit_bindgen::generate!({
+    world: "tiktoken",
+    path: "wit",
+});
+
+use exports::tiktoken::tiktoken::tokenizer::{self, Guest, GuestEncoding, SpecialTokens};
+
+struct Component;
+
+export!(Component);
+
+impl Guest for Component {
+    type Encoding = Encoding;
+
+    fn encoding_names() -> Vec<String> {
+        Encoding::names()
+            .iter()
+            .map(|&name| String::from(name))
+            .collect()
+    }
+}
+
+impl GuestEncoding for Encoding {
+    fn from_ranks(
+        ranks: String,
+        special_tokens: Vec<(String, u32)>,
+        pattern: String,
+    ) -> Result<tokenizer::Encoding, tokenizer::Error> {
+        resource(Encoding::new(&ranks, special_tokens, &pattern))
+    }
+
+    fn from_binary(data: Vec<u8>) -> Result<tokenizer::Encoding, tokenizer::Error> {
+        resource(Encoding::from_binary(&data))
+    }
+
+    fn from_huggingface(tokenizer_json: String) -> Result<tokenizer::Encoding, tokenizer::Error> {
+        resource(Encoding::from_huggingface(&tokenizer_json))
+    }
+
+    fn get(name: String) -> Result<tokenizer::Encoding, tokenizer::Error> {
+        resource(Encoding::get(&name))
+    }
+
+    fn for_model(model: String) -> Result<tokenizer::Encoding, tokenizer::Error> {
+        resource(Encoding::for_model(&model))
+    }
+
+    fn name(&self) -> Option<String> {
+        Encoding::name(self).map(String::from)
+    }
+
+    fn n_vocab(&self) -> u32 {
+        Encoding::n_vocab(self) as u32
+    }
+
+    fn eot_token(&self) -> Option<u32> {
+        Encoding::eot_token(self)
+    }
+
+    fn special_tokens(&self) -> Vec<(String, u32)> {
+        Encoding::special_tokens(self)
+            .into_iter()
+            .map(|(token, rank)| (String::from(token), rank))
+            .collect()
+    }
+
+    fn encode(
+        &self,
+        text: String,
+        allowed_special: SpecialTokens,
+        disallowed_special: SpecialTokens,
+    ) -> Result<Vec<u32>, tokenizer::Error> {
+        encode_with(self, &text, &allowed_special, &disallowed_special).map_err(wit_error)
+    }
+
+    fn encode_ordinary(&self, text: String) -> Vec<u32> {
+        Encoding::encode_ordinary(self, &text)
+    }
+
+    fn count(
+        &self,
+        text: String,
+        allowed_special: SpecialTokens,
+        disallowed_special: SpecialTokens,
+    ) -> Result<u32, tokenizer::Error> {
+        encode_with(self, &text, &allowed_special, &disallowed_special)
+            .map(|tokens| tokens.len() as u32)
+            .map_err(wit_error)
+    }
+
+    fn decode(&self, tokens: Vec<u32>) -> Result<Vec<u8>, tokenizer::Error> {
+        self.decode_bytes(&tokens).map_err(wit_error)
+    }
+}
+
+fn encode_with(
+    encoding: &Encoding,
+    text: &str,
+    allowed_special: &SpecialTokens,
+    disallowed_special: &SpecialTokens,
+) -> Result<Vec<u32>, Error> {
+    let allowed_tokens: Vec<&str>;
+    let allowed = match allowed_special {
+        SpecialTokens::All => AllowedSpecial::All,
+        SpecialTokens::None => AllowedSpecial::None,
+        SpecialTokens::Tokens(tokens) => {
+            allowed_tokens = tokens.iter().map(String::as_str).collect();
+            AllowedSpecial::Tokens(&allowed_tokens)
+        }
+    };
+
+    let disallowed_tokens: Vec<&str>;
+    let disallowed = match disallowed_special {
+        SpecialTokens::All => DisallowedSpecial::All,
+        SpecialTokens::None => DisallowedSpecial::None,
+        SpecialTokens::Tokens(tokens) => {
+            disallowed_tokens = tokens.iter().map(String::as_str).collect();
+            DisallowedSpecial::Tokens(&disallowed_tokens)
+        }
+    };
+
+    encoding.encode(text, allowed, disallowed)
+}
+
+fn resource(encoding: Result<Encoding, Error>) -> Result<tokenizer::Encoding, tokenizer::Error> {
+    encoding.map(tokenizer::Encoding::new).map_err(wit_error)
+}
+
+fn wit_error(error: Error) -> tokenizer::Error {
+    let message = error.to_string();
+    match error {
+        Error::Construction(_) | Error::UnmergeableToken { .. } => {
+            tokenizer::Error::InvalidEncoding(message)
+        }
+        Error::UnknownEncoding { .. } | Error::UnknownModel { .. } => {
+            tokenizer::Error::UnknownEncoding(message)
+        }
+        Error::UnknownSpecialToken { .. } => tokenizer::Error::UnknownSpecialToken(message),
+        Error::DisallowedSpecialToken { .. } => tokenizer::Error::DisallowedSpecialToken(message),
+        Error::NotASingleToken { .. } | Error::UnknownToken { .. } => {
+            tokenizer::Error::UnknownToken(message)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose, Engine as _};
+
+    use super::*;
+
+    // Handles are only created by a component host, so these call the exports on an
+    // `Encoding` directly, and the constructors only where they fail.
+    #[test]
+    fn exports_the_tokenizer_interface() {
+        let mut ranks: String = (0..=255u8)
+            .map(|byte| format!("{} {}\n", general_purpose::STANDARD.encode([byte]), byte))
+            .collect();
+        ranks.push_str("YWI= 256\n");
+        let encoding =
+            Encoding::new(&ranks, vec![(String::from("<|x|>"), 257)], "\\w+|<\\|x\\|>").unwrap();
+
+        assert_eq!(GuestEncoding::n_vocab(&encoding), 258);
+        assert_eq!(GuestEncoding::eot_token(&encoding), None);
+        assert_eq!(
+            GuestEncoding::special_tokens(&encoding),
+            [(String::from("<|x|>"), 257)]
+        );
+
+        let text = String::from("ab<|x|>");
+        let tokens = GuestEncoding::encode(
+            &encoding,
+            text.clone(),
+            SpecialTokens::Tokens(vec![String::from("<|x|>")]),
+            SpecialTokens::All,
+        )
+        .unwrap();
+        assert_eq!(tokens, [256, 257]);
+        assert!(matches!(
+            GuestEncoding::count(
+                &encoding,
+                text.clone(),
+                SpecialTokens::All,
+                SpecialTokens::None
+            ),
+            Ok(2)
+        ));
+        assert_eq!(
+            GuestEncoding::encode_ordinary(&encoding, text.clone()).len(),
+            6
+        );
+
+        assert_eq!(
+            GuestEncoding::decode(&encoding, tokens).unwrap(),
+            text.as_bytes()
+        );
+        assert!(matches!(
+            GuestEncoding::decode(&encoding, vec![258]),
+            Err(tokenizer::Error::UnknownToken(_))
+        ));
+
+        assert!(matches!(
+            <Encoding as GuestEncoding>::from_ranks(
+                String::from("YWI= 0\n"),
+                vec![],
+                String::new()
+            ),
+            Err(tokenizer::Error::InvalidEncoding(_))
+        ));
+        assert!(matches!(
+            <Encoding as GuestEncoding>::get(String::from("gpt-0")),
+            Err(tokenizer::Error::UnknownEncoding(_))
+        ));
+        assert!(matches!(
+            <Encoding as GuestEncoding>::for_model(String::from("gpt-0")),
+            Err(tokenizer::Error::UnknownEncoding(_))
+        ));
+    }
+}
@@ -0,0 +1,355 @@
+//! The JavaScript API, a thin layer over [`Encoding`] that converts arguments from and results
+//! to JS values.
+
+use gloo_utils::format::JsValueSerdeExt;
+use rustc_hash::FxHashMap as HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::encoding::SpecialParams;
+use crate::{CoreBPE, CoreBPEConstructor, Encoding, Rank};
+
+#[wasm_bindgen]
+pub struct Tiktoken {
+    encoding: Encoding,
+}
+
+#[wasm_bindgen]
+impl Tiktoken {
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        tiktoken_bfe: &str,
+        special_tokens: JsValue,
+        pat_str: &str,
+    ) -> Result<Tiktoken, JsError> {
+        Ok(Tiktoken::from(Encoding::new(
+            tiktoken_bfe,
+            special_tokens_from_js(&special_tokens)?,
+            pat_str,
+        )?))
+    }
+
+    /// Loads an encoding, including its name, pattern and special tokens, from the binary
+    /// vocabulary format written by `tiktoken_to_binary`.
+    pub fn from_binary(data: &[u8]) -> Result<Tiktoken, JsError> {
+        Ok(Tiktoken::from(Encoding::from_binary(data)?))
+    }
+
+    /// Converts the byte-level BPE model of a Hugging Face `tokenizer.json`. Added tokens
+    /// marked `special` become special tokens, and the `Split` pre-tokenizer regex becomes the
+    /// pattern. Tokenizers with a normalizer or other processing tiktoken cannot reproduce
+    /// are rejected.
+    pub fn from_huggingface(tokenizer_json: &str) -> Result<Tiktoken, JsError> {
+        Ok(Tiktoken::from(Encoding::from_huggingface(tokenizer_json)?))
+    }
+
+    /// Loads the `encoder.json` and `vocab.bpe` files of the original GPT-2 release, or of a
+    /// model derived from it, with the GPT-2 split pattern. The ids in `encoder.json` must
+    /// increase in the order of the merges in `vocab.bpe`.
+    pub fn from_gpt2(encoder_json: &str, vocab_bpe: &str) -> Result<Tiktoken, JsError> {
+        Ok(Tiktoken::from(Encoding::from_gpt2(
+            encoder_json,
+            vocab_bpe,
+        )?))
+    }
+
+    /// Serializes this encoding, so that `Tiktoken.deserialize` can restore it without parsing
+    /// or sorting the vocabulary again.
+    pub fn serialize(&self) -> Vec<u8> {
+        self.encoding.serialize()
+    }
+
+    /// Restores an encoding written by `serialize`, rejecting snapshots that are corrupt or
+    /// come from an incompatible version.
+    pub fn deserialize(data: &[u8]) -> Result<Tiktoken, JsError> {
+        Ok(Tiktoken::from(Encoding::deserialize(data)?))
+    }
+
+    /// Derives an encoding with additional special tokens. Existing tokens may be given a new id.
+    /// The vocabulary and split pattern are shared with this encoding.
+    pub fn with_special_tokens(&self, special_tokens: JsValue) -> Result<Tiktoken, JsError> {
+        Ok(Tiktoken::from(self.encoding.with_special_tokens(
+            special_tokens_from_js(&special_tokens)?,
+        )?))
+    }
+
+    /// Derives an encoding without the given special tokens, sharing the vocabulary and split
+    /// pattern with this encoding.
+    pub fn without_special_tokens(&self, tokens: Vec<String>) -> Result<Tiktoken, JsError> {
+        let tokens: Vec<&str> = tokens.iter().map(String::as_str).collect();
+        Ok(Tiktoken::from(
+            self.encoding.without_special_tokens(&tokens)?,
+        ))
+    }
+
+    /// Derives an encoding that splits text with `pat_str`, sharing the vocabulary and special
+    /// tokens with this encoding.
+    pub fn with_pattern(&self, pat_str: &str) -> Result<Tiktoken, JsError> {
+        Ok(Tiktoken::from(self.encoding.with_pattern(pat_str)?))
+    }
+
+    /// Derives an identical encoding under a different name.
+    pub fn with_name(&self, name: &str) -> Tiktoken {
+        Tiktoken::from(self.encoding.with_name(name))
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> Option<String> {
+        self.encoding.name().map(String::from)
+    }
+
+    /// The number of token ids, i.e. `max_token_value + 1`.
+    #[wasm_bindgen(getter)]
+    pub fn n_vocab(&self) -> usize {
+        self.encoding.n_vocab()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn max_token_value(&self) -> Rank {
+        self.encoding.max_token_value()
+    }
+
+    /// The id of `<|endoftext|>`, if the encoding has one.
+    #[wasm_bindgen(getter)]
+    pub fn eot_token(&self) -> Option<Rank> {
+        self.encoding.eot_token()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn special_tokens(&self) -> JsValue {
+        JsValue::from_serde(&self.encoding.special_tokens()).unwrap_throw()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn special_tokens_set(&self) -> Vec<String> {
+        self.encoding
+            .special_tokens()
+            .into_keys()
+            .map(String::from)
+            .collect()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn pat_str(&self) -> String {
+        String::from(self.encoding.pattern())
+    }
+
+    /// Writes the ordinary tokens in the `.tiktoken` text format, in rank order. The result can
+    /// be loaded by `new Tiktoken` and by the Python `tiktoken.load.load_tiktoken_bpe`.
+    pub fn export_ranks(&self) -> String {
+        self.encoding.export_ranks()
+    }
+
+    /// Converts the encoding to a Hugging Face `tokenizer.json` with a byte-level BPE model.
+    /// The merges are derived from the ranks, special tokens become added tokens, and the
+    /// pattern becomes a `Split` pre-tokenizer, which Hugging Face evaluates with Oniguruma.
+    pub fn export_huggingface(&self) -> Result<String, JsError> {
+        Ok(self.encoding.export_huggingface()?)
+    }
+
+    /// The rest of the encoding as JSON, with the keyword arguments of the Python `Encoding`:
+    /// `name`, `pat_str`, `special_tokens` and `explicit_n_vocab`.
+    pub fn export_config(&self) -> String {
+        self.encoding.export_config()
+    }
+
+    pub fn encode(
+        &self,
+        text: &str,
+        allowed_special: JsValue,
+        disallowed_special: JsValue,
+    ) -> Result<Vec<Rank>, JsError> {
+        Ok(
+            SpecialParams::from_js(&allowed_special, &disallowed_special)?
+                .apply(|allowed, disallowed| self.encoding.encode(text, allowed, disallowed))?,
+        )
+    }
+
+    pub fn encode_ordinary(&self, text: &str) -> Vec<Rank> {
+        self.encoding.encode_ordinary(text)
+    }
+
+    pub fn encode_with_unstable(
+        &self,
+        text: &str,
+        allowed_special: JsValue,
+        disallowed_special: JsValue,
+    ) -> Result<JsValue, JsError> {
+        let result = SpecialParams::from_js(&allowed_special, &disallowed_special)?.apply(
+            |allowed, disallowed| {
+                self.encoding
+                    .encode_with_unstable(text, allowed, disallowed)
+            },
+        )?;
+
+        JsValue::from_serde(&result).map_err(|e| {
+            JsError::new(&format!(
+                "Failed to serialize encode_with_unstable result: {}",
+                e
+            ))
+        })
+    }
+
+    pub fn encode_single_token(&self, bytes: &[u8]) -> Result<Rank, JsError> {
+        Ok(self.encoding.encode_single_token(bytes)?)
+    }
+
+    #[wasm_bindgen(skip_typescript)]
+    pub fn _encode_single_piece(&self, bytes: &[u8]) -> Vec<Rank> {
+        self.encoding.core().encode_single_piece(bytes)
+    }
+
+    pub fn decode(&self, tokens: Vec<Rank>) -> Result<Vec<u8>, JsError> {
+        Ok(self.encoding.decode_bytes(&tokens)?)
+    }
+
+    pub fn decode_single_token_bytes(&self, token: Rank) -> Result<Vec<u8>, JsError> {
+        Ok(self.encoding.decode_single_token_bytes(token)?)
+    }
+
+    pub fn token_byte_values(&self) -> JsValue {
+        JsValue::from_serde(&self.encoding.token_byte_values()).unwrap_throw()
+    }
+}
+
+impl From<Encoding> for Tiktoken {
+    fn from(encoding: Encoding) -> Self {
+        Tiktoken { encoding }
+    }
+}
+
+fn special_tokens_from_js(value: &JsValue) -> Result<HashMap<String, Rank>, JsError> {
+    Ok(CoreBPEConstructor::parse_special_tokens(
+        value.into_serde::<serde_json::Value>()?,
+    )?)
+}
+
+impl SpecialParams {
+    fn from_js(allowed_special: &JsValue, disallowed_special: &JsValue) -> Result<Self, JsError> {
+        let allowed = match allowed_special.as_string() {
+            Some(value) if value == "all" => None,
+            Some(_) => return Err(JsError::new("Invalid value for allowed_special")),
+            None => Some(allowed_special.into_serde().unwrap_or_default()),
+        };
+
+        let disallowed = match disallowed_special.into_serde::<Vec<String>>() {
+            Ok(tokens) => Some(tokens),
+            Err(_) => match disallowed_special.as_string().as_deref().unwrap_or("all") {
+                "all" => None,
+                _ => return Err(JsError::new("Invalid value for disallowed_special")),
+            },
+        };
+
+        Ok(SpecialParams {
+            allowed,
+            disallowed,
+        })
+    }
+}
+
+#[wasm_bindgen(typescript_custom_section)]
+const _: &str = r#"
+export type ValidationIssue =
+    | { kind: "duplicate_rank"; rank: number; count: number }
+    | { kind: "rank_gap"; start: number; end: number }
+    | { kind: "special_token_rank_collision"; token: string; rank: number }
+    | { kind: "duplicate_special_token_id"; first: string; second: string; rank: number }
+    | { kind: "missing_single_byte_tokens"; bytes: number[] }
+    | { kind: "unexpected_token_count"; expected: number; actual: number }
+    | { kind: "unexpected_max_token_value"; expected: number; actual: number };
+
+export interface ValidationReport {
+    n_tokens: number;
+    max_token_value: number;
+    n_vocab: number;
+    issues: ValidationIssue[];
+}
+
+/**
+ * @param {string} tiktoken_bfe
+ * @param {Record<string, number>} [special_tokens]
+ * @param {number} [explicit_n_vocab]
+ * @returns {ValidationReport}
+ */
+export function validate_encoding(tiktoken_bfe: string, special_tokens?: Record<string, number>, explicit_n_vocab?: number): ValidationReport;
+"#;
+
+#[wasm_bindgen(skip_typescript)]
+pub fn validate_encoding(
+    tiktoken_bfe: &str,
+    special_tokens: JsValue,
+    explicit_n_vocab: Option<usize>,
+) -> Result<JsValue, JsError> {
+    let report = CoreBPEConstructor::validate_bfe(
+        tiktoken_bfe,
+        &special_tokens_from_js(&special_tokens)?,
+        explicit_n_vocab,
+    )?;
+
+    JsValue::from_serde(&report)
+        .map_err(|e| JsError::new(&format!("Failed to serialize validation report: {}", e)))
+}
+
+/// Converts ranks in the `.tiktoken` text format, along with the special tokens and pattern
+/// that complete the encoding, to the binary format read by `Tiktoken.from_binary`.
+#[wasm_bindgen]
+pub fn tiktoken_to_binary(
+    tiktoken_bfe: &str,
+    special_tokens: JsValue,
+    pat_str: &str,
+    name: Option<String>,
+) -> Result<Vec<u8>, JsError> {
+    // Building the encoding rejects ranks that `from_binary` would fail to load
+    let bpe = CoreBPE::new(
+        tiktoken_bfe,
+        special_tokens_from_js(&special_tokens)?,
+        pat_str,
+    )?;
+    Ok(Encoding::from_bpe(name, bpe).to_binary())
+}
+
+#[cfg(feature = "embedded")]
+#[wasm_bindgen(typescript_custom_section)]
+const _: &str = include_str!(concat!(env!("OUT_DIR"), "/embedded.d.ts"));
+
+#[cfg(feature = "embedded")]
+#[wasm_bindgen(typescript_custom_section)]
+const _: &str = r#"
+/**
+ * @param {TiktokenEmbedding} encoding
+ * @param {Record<string, number>} [extend_special_tokens]
+ * @returns {Tiktoken}
+ */
+export function get_encoding(encoding: TiktokenEmbedding, extend_special_tokens?: Record<string, number>): Tiktoken;
+"#;
+
+#[cfg(feature = "embedded")]
+#[wasm_bindgen(skip_typescript)]
+pub fn get_encoding(encoding: &str, extend_special_tokens: JsValue) -> Result<Tiktoken, JsError> {
+    Ok(Tiktoken::from(Encoding::embedded(
+        encoding,
+        special_tokens_from_js(&extend_special_tokens)?,
+    )?))
+}
+
+#[cfg(feature = "embedded")]
+#[wasm_bindgen(typescript_custom_section)]
+const _: &str = r#"
+/**
+ * @param {TiktokenModel} encoding
+ * @param {Record<string, number>} [extend_special_tokens]
+ * @returns {Tiktoken}
+ */
+export function encoding_for_model(model: TiktokenModel, extend_special_tokens?: Record<string, number>): Tiktoken;
+"#;
+
+#[cfg(feature = "embedded")]
+#[wasm_bindgen(skip_typescript)]
+pub fn encoding_for_model(
+    model: &str,
+    extend_special_tokens: JsValue,
+) -> Result<Tiktoken, JsError> {
+    Ok(Tiktoken::from(Encoding::embedded_for_model(
+        model,
+        special_tokens_from_js(&extend_special_tokens)?,
+    )?))
+}
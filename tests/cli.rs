@@ -0,0 +1,123 @@
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+use base64::{engine::general_purpose, Engine as _};
+
+/// Writes a vocabulary of every byte plus `ab`, with `<|end|>` as special token, and returns the
+/// `--vocab` and `--config` arguments for it.
+fn vocab(test: &str) -> Vec<String> {
+    let dir = std::env::temp_dir().join(format!("tiktoken-cli-{}-{}", test, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut ranks: String = (0..=255u8)
+        .map(|byte| format!("{} {}\n", general_purpose::STANDARD.encode([byte]), byte))
+        .collect();
+    ranks.push_str("YWI= 256\n");
+    let ranks_path = dir.join("test.tiktoken");
+    fs::write(&ranks_path, ranks).unwrap();
+
+    let config_path = dir.join("test.json");
+    fs::write(
+        &config_path,
+        r#"{"name": "test", "pat_str": "\\w+|\\s+|<\\|end\\|>", "special_tokens": {"<|end|>": 257}}"#,
+    )
+    .unwrap();
+
+    vec![
+        String::from("--vocab"),
+        path(ranks_path),
+        String::from("--config"),
+        path(config_path),
+    ]
+}
+
+fn path(path: PathBuf) -> String {
+    path.into_os_string().into_string().unwrap()
+}
+
+fn tiktoken(args: &[String], extra: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tiktoken"))
+        .args(args)
+        .args(extra)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn encodes_counts_and_decodes() {
+    let vocab = vocab("roundtrip");
+
+    let output = tiktoken(
+        &vocab,
+        &["encode", "--allowed-special", "all"],
+        b"ab c<|end|>",
+    );
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"256\n32\n99\n257\n");
+
+    let output = tiktoken(&vocab, &["encode", "--output", "json"], b"ab ab");
+    assert_eq!(output.stdout, b"[256,32,256]\n");
+
+    let output = tiktoken(&vocab, &["count"], b"ab ab");
+    assert_eq!(output.stdout, b"3\n");
+
+    let tokens: Vec<u8> = [256u32, 257].iter().flat_map(|t| t.to_le_bytes()).collect();
+    let output = tiktoken(&vocab, &["decode", "--input", "binary"], &tokens);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"ab<|end|>");
+}
+
+#[test]
+fn exit_codes_distinguish_errors() {
+    let vocab = vocab("exit-codes");
+
+    let output = tiktoken(&vocab, &["encode"], b"ab<|end|>");
+    assert_eq!(output.status.code(), Some(3));
+
+    let output = tiktoken(
+        &vocab,
+        &["encode", "--disallowed-special", "none"],
+        b"ab<|end|>",
+    );
+    assert!(output.status.success());
+
+    let output = tiktoken(&vocab, &["count", "does-not-exist.txt"], b"");
+    assert_eq!(output.status.code(), Some(4));
+
+    let output = tiktoken(&vocab, &["decode"], b"1000");
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = tiktoken(&[], &["count"], b"");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn converts_between_formats() {
+    let vocab = vocab("convert");
+    let output = tiktoken(&vocab, &["convert", "--to", "binary"], b"");
+    assert!(output.status.success());
+
+    let dir = std::env::temp_dir().join(format!("tiktoken-cli-convert-{}", std::process::id()));
+    let binary = dir.join("test.bin");
+    fs::write(&binary, output.stdout).unwrap();
+
+    let args = [
+        String::from("--vocab"),
+        path(binary),
+        String::from("--format"),
+        String::from("binary"),
+    ];
+    let output = tiktoken(&args, &["vocab"], b"");
+    let vocab = String::from_utf8(output.stdout).unwrap();
+    assert!(vocab.contains("256\t\"ab\"\n"));
+    assert!(vocab.ends_with("257\t\"<|end|>\" (special)\n"));
+}
@@ -0,0 +1,146 @@
+#![cfg(all(feature = "server", feature = "gpt2"))]
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+struct Server {
+    child: Child,
+    port: u16,
+}
+
+impl Server {
+    fn start(args: &[&str]) -> Self {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let child = Command::new(env!("CARGO_BIN_EXE_tiktoken-server"))
+            .args(["--listen", &format!("127.0.0.1:{}", port)])
+            .args(args)
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let server = Server { child, port };
+
+        for _ in 0..100 {
+            if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+                return server;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        panic!("the server did not start");
+    }
+
+    /// Sends a request and returns the status and the JSON body of the response.
+    fn request(&self, method: &str, path: &str, body: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(("127.0.0.1", self.port)).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            body.len(),
+            body
+        )
+        .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = &response[response.find("\r\n\r\n").unwrap() + 4..];
+        (status, serde_json::from_str(body).unwrap())
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn serves_encodings() {
+    let server = Server::start(&["--encoding", "gpt2", "--max-batch-size", "2"]);
+
+    let (status, body) = server.request("GET", "/encodings", "");
+    assert_eq!(status, 200);
+    assert_eq!(body["encodings"][0]["name"], "gpt2");
+    assert_eq!(body["encodings"][0]["eot_token"], 50256);
+
+    let (status, body) = server.request(
+        "POST",
+        "/encode",
+        r#"{"text": ["hello", "<|endoftext|>"], "allowed_special": "all"}"#,
+    );
+    assert_eq!(status, 200);
+    assert_eq!(body["tokens"][1], json!([50256]));
+
+    let request = json!({ "tokens": body["tokens"][0] }).to_string();
+    let (status, body) = server.request("POST", "/decode", &request);
+    assert_eq!(status, 200);
+    assert_eq!(body["text"], "hello");
+
+    // Characters of several bytes, which are likely to be split across tokens, stay whole
+    for text in [
+        "tokenize this, then chunk it into pieces",
+        "chünk ïnto piéces for 3 €€",
+    ] {
+        let request = json!({ "model": "gpt2", "text": text, "max_tokens": 3 }).to_string();
+        let (status, body) = server.request("POST", "/chunk", &request);
+        assert_eq!(status, 200);
+        let chunks = body["chunks"].as_array().unwrap();
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk["tokens"].as_array().unwrap().len() <= 3));
+        let joined: String = chunks
+            .iter()
+            .map(|chunk| chunk["text"].as_str().unwrap())
+            .collect();
+        assert_eq!(joined, text);
+    }
+}
+
+#[test]
+fn reports_structured_errors() {
+    let server = Server::start(&["--encoding", "gpt2", "--max-batch-size", "2"]);
+
+    let (status, body) = server.request("POST", "/encode", r#"{"text": "<|endoftext|>"}"#);
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["type"], "disallowed_special_token");
+
+    let (status, body) = server.request("POST", "/count", r#"{"text": ["a", "b", "c"]}"#);
+    assert_eq!(status, 413);
+    assert_eq!(body["error"]["type"], "batch_too_large");
+
+    let (status, body) = server.request(
+        "POST",
+        "/count",
+        r#"{"encoding": "cl100k_base", "text": "a"}"#,
+    );
+    assert_eq!(status, 404);
+    assert_eq!(body["error"]["type"], "unknown_encoding");
+
+    // Models resolve only against the loaded encodings
+    let (status, body) = server.request(
+        "POST",
+        "/count",
+        r#"{"model": "text-embedding-ada-002", "text": "a"}"#,
+    );
+    assert_eq!(status, 404);
+    assert_eq!(body["error"]["type"], "unknown_encoding");
+
+    let (status, body) = server.request("POST", "/count", r#"{"model": "gpt-0", "text": "a"}"#);
+    assert_eq!(status, 404);
+    assert_eq!(body["error"]["message"], "Invalid model: gpt-0");
+
+    let (status, body) = server.request("GET", "/encode", "");
+    assert_eq!(status, 405);
+    assert_eq!(body["error"]["type"], "method_not_allowed");
+}
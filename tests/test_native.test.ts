@@ -0,0 +1,41 @@
+import { it, expect, describe } from "vitest";
+import { existsSync } from "fs";
+import { createRequire } from "module";
+
+// The Node-API addon is only present after `yarn build:native`
+const addon = "../dist/native/_tiktoken.js";
+const built = existsSync(new URL(addon, import.meta.url));
+
+describe.runIf(built)("native addon", () => {
+  const { Tiktoken, get_encoding } = createRequire(import.meta.url)(addon);
+
+  it("encodes and decodes a string", () => {
+    const enc = get_encoding("gpt2");
+    const tokens = enc.encode("hello world");
+    expect(tokens).toStrictEqual(new Uint32Array([31373, 995]));
+    expect(new TextDecoder().decode(enc.decode(tokens))).toStrictEqual(
+      "hello world"
+    );
+    enc.free();
+  });
+
+  it("builds custom encodings", () => {
+    // Every byte at its own rank, then "ab"
+    const ranks = Array.from(
+      { length: 256 },
+      (_, byte) => `${btoa(String.fromCharCode(byte))} ${byte}`
+    ).join("\n");
+    const enc = new Tiktoken(
+      `${ranks}\nYWI= 256`,
+      { "<|end|>": 257 },
+      "[a-z]+"
+    );
+    expect(enc.encode("ab<|end|>", "all")).toStrictEqual(
+      new Uint32Array([256, 257])
+    );
+    expect(() => enc.encode("<|end|>")).toThrowError();
+    expect(
+      new TextDecoder().decode(enc.decode(new Uint32Array([256, 257])))
+    ).toStrictEqual("ab<|end|>");
+  });
+});
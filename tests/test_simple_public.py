@@ -24,3 +24,25 @@ def test_encoding_for_model():
     assert enc.name == "gpt2"
     enc = tiktoken.encoding_for_model("text-davinci-003")
     assert enc.name == "p50k_base"
+
+
+def test_custom_encoding():
+    mergeable_ranks = {bytes([b]): b for b in range(256)}
+    mergeable_ranks[b"ab"] = 256
+    enc = tiktoken.Encoding(
+        "custom",
+        pat_str=r"\w+|\s+",
+        mergeable_ranks=mergeable_ranks,
+        special_tokens={"<|endoftext|>": 257},
+    )
+    assert enc.encode("ab ab") == [256, 32, 256]
+    assert enc.encode("ab<|endoftext|>", allowed_special="all") == [256, 257]
+    assert enc.decode_bytes([256, 257]) == b"ab<|endoftext|>"
+    assert enc.encode_single_token("ab") == 256
+
+    try:
+        enc.encode_single_token("abc")
+    except KeyError as error:
+        assert error.args == (b"abc",)
+    else:
+        raise AssertionError("expected a KeyError")
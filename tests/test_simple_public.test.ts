@@ -1,5 +1,21 @@
 import { it, expect, describe } from "vitest";
-import { encoding_for_model, get_encoding } from "../";
+import {
+  clear_encoding_cache,
+  encoding_for_model,
+  get_encoding,
+  Tiktoken,
+  tiktoken_to_binary,
+  validate_encoding,
+} from "../";
+
+// Ranks in the `.tiktoken` format that give every byte its value as rank, followed by `tokens`
+const byteRanks = (...tokens: string[]) =>
+  [
+    ...Array.from({ length: 256 }, (_, byte) => String.fromCharCode(byte)),
+    ...tokens,
+  ]
+    .map((token, rank) => `${btoa(token)} ${rank}`)
+    .join("\n");
 
 it("encoding_for_model initialization", () => {
   expect(() => encoding_for_model("gpt2")).not.toThrowError();
@@ -134,3 +150,287 @@ it("invalid (dis)allowed_tokens", () => {
     "Invalid value for disallowed_special"
   );
 });
+
+it("rejects malformed ranks and patterns", () => {
+  expect(() => new Tiktoken("YQ== 0\nYg== 1", {}, "\\s+")).toThrowError(
+    "have no single-byte token"
+  );
+
+  expect(() => new Tiktoken("YQ== 0\nYg==", {}, "\\s+")).toThrowError(
+    "Malformed line 2"
+  );
+
+  expect(() => new Tiktoken("YQ== 0\n!!! 1", {}, "\\s+")).toThrowError(
+    "Invalid base64 token on line 2"
+  );
+
+  expect(() => new Tiktoken("YQ== 0\nYg== 0", {}, "\\s+")).toThrowError(
+    "Duplicate rank on line 2: 0"
+  );
+
+  expect(() => new Tiktoken("YQ== 0", {}, "a(b")).toThrowError(
+    "Invalid pattern at position"
+  );
+});
+
+it("rejects malformed special tokens", () => {
+  expect(() =>
+    // @ts-expect-error
+    get_encoding("gpt2", { "<|im_start|>": "100264", "<|im_end|>": -1 })
+  ).toThrowError(
+    'Invalid special tokens: "<|im_end|>", "<|im_start|>" must have non-empty names'
+  );
+
+  expect(() => get_encoding("gpt2", { "<|im_start|>": 50256 })).toThrowError(
+    'Special tokens "<|endoftext|>" and "<|im_start|>" share the same id 50256'
+  );
+
+  expect(() => new Tiktoken("YQ== 0", { "<|a|>": 0 }, "\\s+")).toThrowError(
+    'Special token "<|a|>" collides with the ordinary token of rank 0'
+  );
+});
+
+it("validate_encoding", () => {
+  const report = validate_encoding("YQ== 0\nYg== 1", { "<|eot|>": 3 }, 4);
+  expect(report.n_tokens).toEqual(3);
+  expect(report.max_token_value).toEqual(3);
+  expect(report.issues).toContainEqual({ kind: "rank_gap", start: 2, end: 3 });
+  expect(report.issues).toContainEqual({
+    kind: "unexpected_token_count",
+    expected: 4,
+    actual: 3,
+  });
+
+  expect(validate_encoding("YQ== 0\nYg== 0").issues).toContainEqual({
+    kind: "duplicate_rank",
+    rank: 0,
+    count: 2,
+  });
+});
+
+it("exposes encoding metadata", () => {
+  const gpt2 = get_encoding("gpt2", { "<|im_start|>": 100264 });
+  expect(gpt2.eot_token).toEqual(50256);
+  expect(gpt2.max_token_value).toEqual(100264);
+  expect(gpt2.n_vocab).toEqual(100265);
+  expect(gpt2.special_tokens).toStrictEqual({
+    "<|endoftext|>": 50256,
+    "<|im_start|>": 100264,
+  });
+  expect(gpt2.special_tokens_set).toStrictEqual([
+    "<|endoftext|>",
+    "<|im_start|>",
+  ]);
+
+  const cl100k = get_encoding("cl100k_base");
+  expect(cl100k.eot_token).toEqual(100257);
+  expect(cl100k.n_vocab).toEqual(100277);
+  expect(cl100k.pat_str).toContain("\\p{N}{1,3}");
+
+  const custom = new Tiktoken(byteRanks(), {}, "\\s+");
+  expect(custom.eot_token).toBeUndefined();
+  expect(custom.n_vocab).toEqual(256);
+  expect(custom.pat_str).toEqual("\\s+");
+});
+
+it("derives encodings with the builder methods", () => {
+  const base = get_encoding("cl100k_base");
+  const chat = base
+    .with_special_tokens({ "<|im_start|>": 100264, "<|im_end|>": 100265 })
+    .with_name("cl100k_chat");
+
+  expect(chat.name).toEqual("cl100k_chat");
+  expect(base.name).toEqual("cl100k_base");
+  expect(chat.encode("<|im_start|>hello<|im_end|>", "all")).toStrictEqual(
+    new Uint32Array([100264, 15339, 100265])
+  );
+  expect(() => base.encode("<|im_start|>", "all")).not.toThrowError();
+  expect(base.special_tokens_set).not.toContain("<|im_start|>");
+
+  const noFim = chat.without_special_tokens([
+    "<|fim_prefix|>",
+    "<|fim_middle|>",
+    "<|fim_suffix|>",
+  ]);
+  expect(noFim.special_tokens_set).toStrictEqual([
+    "<|endofprompt|>",
+    "<|endoftext|>",
+    "<|im_end|>",
+    "<|im_start|>",
+  ]);
+  expect(() => noFim.without_special_tokens(["<|fim_prefix|>"])).toThrowError(
+    "Unknown special token: <|fim_prefix|>"
+  );
+
+  const renumbered = chat.with_special_tokens({ "<|im_start|>": 200000 });
+  expect(renumbered.special_tokens["<|im_start|>"]).toEqual(200000);
+  expect(() => chat.with_special_tokens({ "<|im_end|>": 100257 })).toThrowError(
+    "share the same id 100257"
+  );
+
+  const words = base.with_pattern("\\S+|\\s+");
+  expect(words.pat_str).toEqual("\\S+|\\s+");
+  expect(() => base.with_pattern("(")).toThrowError("Invalid pattern");
+});
+
+it("shares parsed vocabularies until the cache is cleared", () => {
+  const first = get_encoding("cl100k_base");
+  const second = encoding_for_model("text-embedding-ada-002");
+  expect(second.encode("hello world")).toStrictEqual(
+    first.encode("hello world")
+  );
+
+  clear_encoding_cache();
+  expect(first.encode("hello world")).toStrictEqual(
+    new Uint32Array([15339, 1917])
+  );
+  expect(get_encoding("cl100k_base").encode("hello world")).toStrictEqual(
+    new Uint32Array([15339, 1917])
+  );
+});
+
+it("loads encodings from the binary vocabulary format", () => {
+  const data = tiktoken_to_binary(
+    byteRanks("ab"),
+    { "<|end|>": 257 },
+    "[a-z]+",
+    "ab"
+  );
+  expect(data).toBeInstanceOf(Uint8Array);
+
+  const enc = Tiktoken.from_binary(data);
+  expect(enc.name).toEqual("ab");
+  expect(enc.pat_str).toEqual("[a-z]+");
+  expect(enc.special_tokens).toStrictEqual({ "<|end|>": 257 });
+  expect(enc.encode("ab <|end|>", "all")).toStrictEqual(
+    new Uint32Array([256, 257])
+  );
+
+  expect(() =>
+    Tiktoken.from_binary(data.subarray(0, data.length - 1))
+  ).toThrowError("Malformed binary vocabulary");
+  expect(() =>
+    tiktoken_to_binary("YQ== 0", { "<|end|>": 0 }, "[a-z]+")
+  ).toThrowError("collides with the ordinary token of rank 0");
+});
+
+it("restores serialized encodings", () => {
+  const enc = get_encoding("cl100k_base").with_special_tokens({
+    "<|im_start|>": 100264,
+  });
+  const data = enc.serialize();
+  expect(data).toBeInstanceOf(Uint8Array);
+
+  const restored = Tiktoken.deserialize(data);
+  expect(restored.name).toEqual(enc.name);
+  expect(restored.pat_str).toEqual(enc.pat_str);
+  expect(restored.special_tokens).toStrictEqual(enc.special_tokens);
+  expect(restored.encode("hello <|im_start|>", "all")).toStrictEqual(
+    enc.encode("hello <|im_start|>", "all")
+  );
+
+  const corrupted = data.slice();
+  corrupted[corrupted.length - 1] ^= 1;
+  expect(() => Tiktoken.deserialize(corrupted)).toThrowError(
+    "Snapshot checksum mismatch"
+  );
+});
+
+it("exports ranks that load back into an identical encoding", () => {
+  const enc = get_encoding("gpt2").with_special_tokens({ "<|im_end|>": 50257 });
+  const config = JSON.parse(enc.export_config());
+  expect(config).toStrictEqual({
+    name: "gpt2",
+    pat_str: enc.pat_str,
+    special_tokens: { "<|endoftext|>": 50256, "<|im_end|>": 50257 },
+    explicit_n_vocab: 50258,
+  });
+
+  const ranks = enc.export_ranks();
+  expect(ranks.split("\n", 2)).toStrictEqual(["IQ== 0", "Ig== 1"]);
+
+  const reloaded = new Tiktoken(ranks, config.special_tokens, config.pat_str);
+  expect(reloaded.encode("hello world <|im_end|>", "all")).toStrictEqual(
+    enc.encode("hello world <|im_end|>", "all")
+  );
+});
+
+it("imports Hugging Face byte-level BPE tokenizers", () => {
+  // Every byte plus "ab" and " ab", in the byte-level alphabet
+  const { vocab, merges } = JSON.parse(
+    new Tiktoken(byteRanks("ab", " ab"), {}, "").export_huggingface()
+  ).model;
+  const tokenizer = {
+    added_tokens: [{ id: 258, content: "<|end|>", special: true }],
+    normalizer: null,
+    pre_tokenizer: {
+      type: "ByteLevel",
+      add_prefix_space: false,
+      use_regex: true,
+    },
+    model: { type: "BPE", vocab, merges },
+  };
+
+  const enc = Tiktoken.from_huggingface(JSON.stringify(tokenizer));
+  expect(enc.special_tokens).toStrictEqual({ "<|end|>": 258 });
+  expect(enc.encode("ab ab<|end|>", "all")).toStrictEqual(
+    new Uint32Array([256, 257, 258])
+  );
+
+  expect(() =>
+    Tiktoken.from_huggingface(
+      JSON.stringify({ ...tokenizer, normalizer: { type: "Lowercase" } })
+    )
+  ).toThrowError("Unsupported normalizer Lowercase");
+});
+
+it("loads GPT-2 encoder.json and vocab.bpe files", () => {
+  // Every byte plus "ab" and " ab", in the byte-level alphabet
+  const { vocab } = JSON.parse(
+    new Tiktoken(byteRanks("ab", " ab"), {}, "").export_huggingface()
+  ).model;
+  const encoderJson = JSON.stringify({ ...vocab, "<|endoftext|>": 258 });
+
+  const enc = Tiktoken.from_gpt2(encoderJson, "#version: 0.2\na b\nĠ ab\n");
+  expect(enc.eot_token).toEqual(258);
+  expect(enc.encode("ab ab<|endoftext|>", "all")).toStrictEqual(
+    new Uint32Array([256, 257, 258])
+  );
+
+  expect(() =>
+    Tiktoken.from_gpt2(encoderJson, "#version: 0.2\nĠ ab\na b\n")
+  ).toThrowError("Merge 2 produces");
+});
+
+it("exports Hugging Face tokenizers that import back", () => {
+  const enc = new Tiktoken(
+    byteRanks("ab", " ab"),
+    { "<|end|>": 258 },
+    " ?[a-z]+"
+  );
+  const tokenizer = JSON.parse(enc.export_huggingface());
+  expect(Object.keys(tokenizer.model.vocab)).toHaveLength(258);
+  expect(tokenizer.model.vocab).toMatchObject({
+    "Ġ": 32,
+    a: 97,
+    b: 98,
+    ab: 256,
+    "Ġab": 257,
+  });
+  expect(tokenizer.model.merges).toStrictEqual(["a b", "Ġ ab"]);
+
+  const reloaded = Tiktoken.from_huggingface(JSON.stringify(tokenizer));
+  expect(reloaded.encode("ab ab<|end|>", "all")).toStrictEqual(
+    enc.encode("ab ab<|end|>", "all")
+  );
+});
+
+it("rejects unknown tokens when decoding", () => {
+  const enc = new Tiktoken(byteRanks(), { "<|end|>": 256 }, "[a-z]+");
+  expect(enc.decode(new Uint32Array([97, 256]))).toStrictEqual(
+    new TextEncoder().encode("a<|end|>")
+  );
+  expect(() => enc.decode(new Uint32Array([97, 257]))).toThrowError(
+    "Token not found in the vocabulary: 257"
+  );
+});